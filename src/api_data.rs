@@ -61,90 +61,41 @@ pub struct Video {
     pub commentableUserTypeForPayment: Option<String>,
 }
 
-adstruct! {
-    Client {
-        nicosid: String,
-        watchId: String,
-        watchTrackId: String,
-    }
-
-    AudioLoudness {
-        integratedLoudness: f64,
-        truePeak: f64,
-    }
-
-    TypewiseLoudness {
-        r#type: String,
-        value: f64,
-    }
-
-    AudioMetadata {
-        bitrate: i32,
-        samplingRate: i32,
-        loudness: AudioLoudness,
-        levelIndex: i32,
-        loudnessCollection: Vec<TypewiseLoudness>,
-    }
+/// `comment` of the watch API. Only `nvComment` is used, the rest is kept as is for archival.
+#[derive(Serialize, Deserialize, Debug)]
+#[allow(non_snake_case)]
+pub struct WatchComment {
+    pub nvComment: NvComment,
+    #[serde(flatten)]
+    pub others: serde_json::Map<String, Value>,
+}
 
-    MovieAudio {
+adstruct! {
+    NvCommentTarget {
         id: String,
-        isAvailable: bool,
-        metadata: AudioMetadata,
+        fork: String,
     }
 
-    VideoResolution {
-        width: i32,
-        height: i32,
+    NvCommentParams {
+        targets: Vec<NvCommentTarget>,
+        language: String,
     }
 
-    VideoMetadata {
-        label: String,
-        bitrate: i32,
-        resolution: VideoResolution,
-        levelIndex: i32,
-        recommendedHighestAudioLevelIndex: i32
-    }
-
-    MovieVideo {
-        id: String,
-        isAvailable: bool,
-        metadata: VideoMetadata
-    }
-
-    SessionURL {
-        url: String,
-        isWellKnownPort: bool,
-        isSsl: bool
+    NvComment {
+        threadKey: String,
+        server: String,
+        params: NvCommentParams,
     }
 
-    Session {
-        recipeId: String,
-        playerId: String,
-        videos: Vec<String>,
-        audios: Vec<String>,
-        movies: Vec<String>,
-        protocols: Vec<String>,
-        authTypes: Value,
-        serviceUserId: String,
-        token: String,
-        signature: String,
-        contentId: String,
-        heartbeatLifetime: i32,
-        contentKeyTimeout: i32,
-        priority: f32,
-        transferPresets: Vec<String>,
-        urls: Vec<SessionURL>
-    }
-
-    StoryBoardImage {
-        id: String
+    Client {
+        nicosid: String,
+        watchId: String,
+        watchTrackId: String,
     }
 
-    StoryBoard {
-        contentId: String,
-        images: Vec<StoryBoardImage>,
-        session: Session,
-
+    TypewiseLoudness {
+        r#type: String,
+        value: f64,
     }
 
     DomandVideo {
@@ -177,7 +128,7 @@ adstruct! {
     }
 
     Media {
-        domand: Domand,
+        domand: Option<Domand>,
         delivery: Value,
         deliveryLegacy: Option<Value>
     }
@@ -241,7 +192,7 @@ adstruct! {
         category: Option<Value>,
         channel: Option<Value>,
         client: Client,
-        comment: Option<Value>,
+        comment: Option<WatchComment>,
         community: Option<Value>,
         easyComment: Option<Value>,
         external: Option<Value>,
@@ -266,3 +217,102 @@ adstruct! {
         waku: Option<Value>,
     }
 }
+
+impl VideoThumbnail {
+    /// The largest thumbnail: `largeUrl`, then the OGP image, `middleUrl` and `url`.
+    pub fn largest_url(&self) -> &str {
+        self.largeUrl
+            .as_deref()
+            .or_else(|| self.ogp.as_ref().and_then(|x| x.as_str()))
+            .or(self.middleUrl.as_deref())
+            .unwrap_or(&self.url)
+    }
+}
+
+impl ApiData {
+    /// Uploader name: the user nickname, or the channel name for channel videos.
+    pub fn owner_name(&self) -> Option<&str> {
+        self.owner
+            .as_ref()
+            .and_then(|x| x["nickname"].as_str())
+            .or_else(|| self.channel.as_ref().and_then(|x| x["name"].as_str()))
+    }
+
+    /// Uploader id: the user id, or the channel id (`ch...`) for channel videos.
+    pub fn owner_id(&self) -> Option<String> {
+        let id = |x: &Value| match &x["id"] {
+            Value::String(x) => Some(x.clone()),
+            Value::Number(x) => Some(x.to_string()),
+            _ => None,
+        };
+        self.owner
+            .as_ref()
+            .and_then(id)
+            .or_else(|| self.channel.as_ref().and_then(id))
+    }
+
+    pub fn is_channel_video(&self) -> bool {
+        self.channel.as_ref().is_some_and(|x| !x.is_null())
+    }
+
+    /// Only a preview (or nothing) is served: the video has to be purchased or needs
+    /// a channel membership.
+    pub fn is_payment_required(&self) -> bool {
+        self.media.domand.is_none() || self.okReason.as_deref() == Some("PAYMENT_PREVIEW_SUPPORTED")
+    }
+
+    pub fn tag_names(&self) -> Vec<&str> {
+        self.tag["items"]
+            .as_array()
+            .map(|x| x.iter().filter_map(|x| x["name"].as_str()).collect())
+            .unwrap_or_default()
+    }
+
+    pub fn watch_url(&self) -> String {
+        format!("https://www.nicovideo.jp/watch/{}", self.video.id)
+    }
+
+    /// ffmpeg `-metadata` tags of the output video.
+    pub fn metadata_tags(&self) -> Vec<(&'static str, String)> {
+        let mut tags = vec![
+            ("title", self.video.title.clone()),
+            ("date", self.video.registeredAt.clone()),
+            ("comment", strip_html(&self.video.description)),
+            ("episode_id", self.video.id.clone()),
+            ("purl", self.watch_url()),
+        ];
+        if let Some(owner) = self.owner_name() {
+            tags.push(("artist", owner.to_string()));
+        }
+        let tag_names = self.tag_names();
+        if !tag_names.is_empty() {
+            tags.push(("genre", tag_names.join(", ")));
+        }
+        tags
+    }
+}
+
+/// Video descriptions are HTML: keeps the text and line breaks only.
+pub fn strip_html(html: &str) -> String {
+    let html = html
+        .replace("<br />", "\n")
+        .replace("<br/>", "\n")
+        .replace("<br>", "\n");
+    scraper::Html::parse_fragment(&html)
+        .root_element()
+        .text()
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn test_strip_html() {
+        use crate::api_data::strip_html;
+
+        assert_eq!(
+            strip_html("<b>Hello</b> &amp; <a href=\"https://example.com\">sm9</a><br />2nd line"),
+            "Hello & sm9\n2nd line"
+        );
+    }
+}
@@ -0,0 +1,128 @@
+use crate::Error;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ArchiveFile {
+    pub path: String,
+    pub size: u64,
+    pub sha1: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ArchiveEntry {
+    pub id: String,
+    pub downloaded_at: String,
+    pub quality: Option<String>,
+    pub files: Vec<ArchiveFile>,
+}
+
+/// Append-only JSONL record of the videos and images downloaded so far (`--download-archive`).
+/// Items are skipped by id, wherever their files are now.
+#[derive(Debug)]
+pub struct Archive {
+    path: PathBuf,
+    entries: Mutex<HashMap<String, ArchiveEntry>>,
+}
+
+impl Archive {
+    pub fn load(path: &Path) -> Result<Archive, Error> {
+        let mut entries = HashMap::new();
+        if path.exists() {
+            for line in BufReader::new(fs::File::open(path)?).lines() {
+                if let Ok(entry) = serde_json::from_str::<ArchiveEntry>(&line?) {
+                    entries.insert(entry.id.clone(), entry);
+                }
+            }
+        }
+        Ok(Archive {
+            path: path.to_path_buf(),
+            entries: Mutex::new(entries),
+        })
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.entries.lock().unwrap().contains_key(id)
+    }
+
+    /// Records `id` with the size and SHA-1 of the `files` that exist.
+    pub fn record(
+        &self,
+        id: &str,
+        quality: Option<String>,
+        files: &[PathBuf],
+    ) -> Result<(), Error> {
+        let mut archived = vec![];
+        for path in files.iter().filter(|x| x.exists()) {
+            let mut hasher = Sha1::new();
+            let size = io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+            archived.push(ArchiveFile {
+                path: path.to_string_lossy().to_string(),
+                size,
+                sha1: hex::encode(hasher.finalize()),
+            });
+        }
+        let entry = ArchiveEntry {
+            id: id.to_string(),
+            downloaded_at: chrono::Local::now().to_rfc3339(),
+            quality,
+            files: archived,
+        };
+        if let Some(parent) = self.path.parent().filter(|x| !x.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let mut f = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(f, "{}", serde_json::to_string(&entry)?)?;
+        self.entries.lock().unwrap().insert(entry.id.clone(), entry);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn test_archive() {
+        use crate::archive::Archive;
+        use crate::manifest::sha1_hex;
+        use std::fs;
+
+        let dir = std::env::temp_dir().join(format!("nv_archive_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("archive.jsonl");
+        let video = dir.join("sm9.mp4");
+        fs::write(&video, b"video").unwrap();
+
+        let archive = Archive::load(&path).unwrap();
+        assert!(!archive.contains("sm9"));
+        archive
+            .record(
+                "sm9",
+                Some("720p".to_string()),
+                &[video.clone(), dir.join("missing.jpg")],
+            )
+            .unwrap();
+        // the loaded archive is kept up to date without reading the file again
+        assert!(archive.contains("sm9"));
+
+        // moved files do not matter
+        fs::remove_file(&video).unwrap();
+        let archive = Archive::load(&path).unwrap();
+        assert!(archive.contains("sm9"));
+        assert!(!archive.contains("sm10"));
+        let entry = archive.entries.lock().unwrap()["sm9"].clone();
+        assert_eq!(entry.quality.as_deref(), Some("720p"));
+        assert_eq!(entry.files.len(), 1);
+        assert_eq!(entry.files[0].size, 5);
+        assert_eq!(entry.files[0].sha1, sha1_hex(b"video"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,8 @@
+use serde::{Deserialize, Serialize};
+
+/// A video found in the video listing of a channel.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ChannelVideo {
+    pub id: String,
+    pub title: String,
+}
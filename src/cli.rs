@@ -0,0 +1,371 @@
+use crate::http::RetryPolicy;
+use crate::overwrite::OverwritePolicy;
+use crate::quality::{AudioQuality, QualitySelector, VideoQuality};
+use crate::target::Target;
+use crate::template::{OutputKind, OutputTemplates, Template, parse_output};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Largest `--max-bitrate` whose value in bps still fits the `bitRate` of the API
+const MAX_BITRATE_KBPS: i64 = i32::MAX as i64 / 1000;
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Downloader for niconico videos and seiga images")]
+pub struct Cli {
+    #[command(flatten)]
+    pub account: Account,
+
+    #[command(flatten)]
+    pub options: Options,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Args)]
+pub struct Account {
+    /// Mail address or phone number used to login
+    #[arg(long, global = true, env = "NV_USERNAME", hide_env_values = true)]
+    pub username: Option<String>,
+
+    /// Password used to login
+    #[arg(long, global = true, env = "NV_PASSWORD", hide_env_values = true)]
+    pub password: Option<String>,
+
+    /// Base32 encoded TOTP secret for 2-step verification
+    #[arg(long, global = true, env = "NV_TOTP_SECRET", hide_env_values = true)]
+    pub totp_secret: Option<String>,
+
+    /// Path of the cookie jar
+    #[arg(long, global = true, default_value = "cookies.json")]
+    pub cookies: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct Options {
+    /// Keep existing files and skip the item (default when stdin is not a terminal)
+    #[arg(long, global = true, conflicts_with_all = ["overwrite", "rename", "ask"])]
+    pub skip_existing: bool,
+
+    /// Overwrite existing files
+    #[arg(long, global = true, conflicts_with_all = ["rename", "ask"])]
+    pub overwrite: bool,
+
+    /// Write into `name (N).ext` when the file exists
+    #[arg(long, global = true, conflicts_with = "ask")]
+    pub rename: bool,
+
+    /// Ask before overwriting existing files (default when stdin is a terminal)
+    #[arg(long, global = true)]
+    pub ask: bool,
+
+    /// Number of HLS segments downloaded in parallel
+    #[arg(short, long, global = true, default_value_t = 4)]
+    pub jobs: usize,
+
+    /// Minimum interval between segment requests in milliseconds
+    #[arg(long, global = true, default_value_t = 250)]
+    pub segment_interval: u64,
+
+    /// Video quality: `best`, `worst` or a label such as `720p`
+    #[arg(long, global = true, default_value = "best")]
+    pub quality: VideoQuality,
+
+    /// Only consider videos up to this height
+    #[arg(long, global = true)]
+    pub max_height: Option<i32>,
+
+    /// Only consider videos up to this bitrate in kbps
+    #[arg(long, global = true, value_parser = clap::value_parser!(i32).range(1..=MAX_BITRATE_KBPS))]
+    pub max_bitrate: Option<i32>,
+
+    /// Audio quality: `best`, `worst` or `recommended` for the chosen video
+    #[arg(long, global = true, default_value = "best")]
+    pub audio_quality: AudioQuality,
+
+    /// Print available video/audio formats instead of downloading
+    #[arg(long, global = true)]
+    pub list_formats: bool,
+
+    /// Formats comments are written in
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        value_delimiter = ',',
+        default_value = "json"
+    )]
+    pub comment_format: Vec<CommentFormat>,
+
+    /// Comment forks to export
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        value_delimiter = ',',
+        default_value = "owner,main,easy"
+    )]
+    pub comment_forks: Vec<CommentFork>,
+
+    /// Also render ASS/XML comments of each fork into `<id>.<fork>.ass`/`.xml`
+    #[arg(long, global = true)]
+    pub split_comment_forks: bool,
+
+    /// JSONL file recording downloaded videos and images, which are skipped next time
+    #[arg(long, global = true)]
+    pub download_archive: Option<PathBuf>,
+
+    /// Output path template as `[KIND:]TEMPLATE`, KIND being video (default), thumbnail,
+    /// nfo, comments, metadata, series, tvshow, mylist, seiga or seiga-metadata. Fields: {id},
+    /// {title}, {owner}, {owner_id}, {date:%Y-%m-%d}, {series}, {series_index},
+    /// {quality}, {group} (seiga tag/clip) and {ext}, which is appended when missing
+    #[arg(short, long, global = true, value_parser = parse_output)]
+    pub output: Vec<(OutputKind, Template)>,
+
+    /// Container of the output video
+    #[arg(long, global = true, value_enum, default_value = "mp4")]
+    pub container: Container,
+
+    /// Save the largest thumbnail next to the output video
+    #[arg(long, global = true)]
+    pub write_thumbnail: bool,
+
+    /// Attach the largest thumbnail to the output video as cover art
+    #[arg(long, global = true)]
+    pub embed_thumbnail: bool,
+
+    /// Write Kodi/Jellyfin `.nfo` files next to the output videos and series
+    #[arg(long, global = true)]
+    pub write_nfo: bool,
+
+    /// Mux the comments into the output video as a soft subtitle track
+    #[arg(long, global = true)]
+    pub embed_comments: bool,
+
+    /// Also render the ASS comments into the video as `<id>_<title>.commented.mp4`
+    #[arg(long, global = true)]
+    pub burn_comments: bool,
+
+    /// JSON file of NG words, users and commands dropped from the exported comments
+    #[arg(long, global = true)]
+    pub comment_filter: Option<PathBuf>,
+
+    /// Also fetch past comments back to the posting date and write them per fork
+    #[arg(long, global = true)]
+    pub comment_history: bool,
+
+    /// How many times a failed HTTP request is retried
+    #[arg(long, global = true, default_value_t = 5)]
+    pub retries: u32,
+
+    /// Initial delay before retrying a failed HTTP request in milliseconds, doubled on each retry
+    #[arg(long, global = true, default_value_t = 1000)]
+    pub retry_delay: u64,
+
+    /// Upper bound of the delay between retries in seconds
+    #[arg(long, global = true, default_value_t = 60)]
+    pub max_retry_delay: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CommentFormat {
+    /// Raw nvComment response
+    Json,
+    /// Danmaku-style ASS subtitles
+    Ass,
+    /// Classic niconico `<packet><chat>` XML
+    Xml,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Container {
+    /// Comments are embedded as mov_text, losing their positions and colors
+    Mp4,
+    /// Comments are embedded as ASS as they are
+    Mkv,
+}
+
+impl Container {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Container::Mp4 => "mp4",
+            Container::Mkv => "mkv",
+        }
+    }
+
+    pub fn subtitle_codec(&self) -> &'static str {
+        match self {
+            Container::Mp4 => "mov_text",
+            Container::Mkv => "ass",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CommentFork {
+    /// Comments by the uploader, often lyrics or credits
+    Owner,
+    /// Comments by viewers
+    Main,
+    /// Comments posted with the easy comment buttons
+    Easy,
+}
+
+impl CommentFork {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CommentFork::Owner => "owner",
+            CommentFork::Main => "main",
+            CommentFork::Easy => "easy",
+        }
+    }
+}
+
+impl Options {
+    pub fn overwrite_policy(&self) -> OverwritePolicy {
+        if self.skip_existing {
+            OverwritePolicy::Skip
+        } else if self.overwrite {
+            OverwritePolicy::Overwrite
+        } else if self.rename {
+            OverwritePolicy::Rename
+        } else if self.ask {
+            OverwritePolicy::Ask
+        } else {
+            OverwritePolicy::detect()
+        }
+    }
+
+    /// The ASS comments are needed for the output or for muxing/burning them in.
+    pub fn renders_ass(&self) -> bool {
+        self.comment_format.contains(&CommentFormat::Ass)
+            || self.burn_comments
+            || self.embed_comments
+    }
+
+    pub fn output_templates(&self) -> OutputTemplates {
+        OutputTemplates::new(self.output.clone())
+    }
+
+    pub fn has_comment_fork(&self, fork: &str) -> bool {
+        self.comment_forks.iter().any(|x| x.as_str() == fork)
+    }
+
+    pub fn segment_interval(&self) -> Duration {
+        Duration::from_millis(self.segment_interval)
+    }
+
+    pub fn quality_selector(&self) -> QualitySelector {
+        QualitySelector {
+            video: self.quality.clone(),
+            max_height: self.max_height,
+            max_bitrate: self.max_bitrate.map(|x| x * 1000),
+            audio: self.audio_quality,
+        }
+    }
+
+    pub fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            retries: self.retries,
+            base_delay: Duration::from_millis(self.retry_delay),
+            max_delay: Duration::from_secs(self.max_retry_delay),
+        }
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Download targets given as ids, URLs or `series/ID`, `mylist/ID`, `user/ID`, `channel/ID`, `my/watch-later`, `my/mylists`, `my/likes`, `clip/ID`, `seiga-tag#TAG`
+    Get {
+        #[arg(required = true, value_name = "TARGET")]
+        targets: Vec<Target>,
+
+        /// Page number to start crawling seiga tags from
+        #[arg(long, env = "NV_SEIGA_PAGE", default_value_t = 1)]
+        page: i32,
+    },
+    /// Download videos (smXXX / nmXXX / soXXX)
+    Video {
+        #[arg(required = true, value_name = "VIDEO_ID")]
+        ids: Vec<String>,
+    },
+    /// Show available video/audio formats without downloading
+    Formats {
+        #[arg(required = true, value_name = "VIDEO_ID")]
+        ids: Vec<String>,
+
+        /// Print as JSON instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+    /// Download every video of series
+    Series {
+        #[arg(required = true, value_name = "SERIES_ID")]
+        ids: Vec<String>,
+    },
+    /// Download every video of mylists
+    Mylist {
+        #[arg(required = true, value_name = "MYLIST_ID")]
+        ids: Vec<String>,
+    },
+    /// Download the watch-later list of the logged-in user
+    WatchLater,
+    /// Download every mylist of the logged-in user
+    MyMylists,
+    /// Download the like history of the logged-in user
+    Likes,
+    /// Download every video of channels (chXXX or the channel's slug)
+    Channel {
+        #[arg(required = true, value_name = "CHANNEL")]
+        ids: Vec<String>,
+    },
+    /// Download every video uploaded by users
+    User {
+        #[arg(required = true, value_name = "USER_ID")]
+        ids: Vec<String>,
+    },
+    /// Download seiga images (imXXX)
+    Seiga {
+        #[arg(required = true, value_name = "IMAGE_ID")]
+        ids: Vec<String>,
+    },
+    /// Download every image of seiga clips
+    Clip {
+        #[arg(required = true, value_name = "CLIP_ID")]
+        ids: Vec<String>,
+    },
+    /// Download every image tagged with the tags
+    SeigaTag {
+        #[arg(required = true, value_name = "TAG")]
+        tags: Vec<String>,
+
+        /// Page number to start crawling from
+        #[arg(long, env = "NV_SEIGA_PAGE", default_value_t = 1)]
+        page: i32,
+    },
+    /// Login and save the session into the cookie jar
+    Login,
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn test_cli() {
+        use crate::cli::Cli;
+        use clap::CommandFactory;
+
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_max_bitrate() {
+        use crate::cli::Cli;
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["nv", "--max-bitrate", "2000", "login"]).unwrap();
+        assert_eq!(cli.options.quality_selector().max_bitrate, Some(2_000_000));
+        assert!(Cli::try_parse_from(["nv", "--max-bitrate", "3000000", "login"]).is_err());
+        assert!(Cli::try_parse_from(["nv", "--max-bitrate", "0", "login"]).is_err());
+    }
+}
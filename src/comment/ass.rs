@@ -0,0 +1,343 @@
+use crate::comment::{Comment, Thread};
+use std::fmt::Write;
+
+/// Scrolling comments stay on the screen for this long.
+const SCROLL_DURATION_MS: i64 = 4000;
+/// `ue`/`shita` comments stay on the screen for this long.
+const FIXED_DURATION_MS: i64 = 3000;
+/// Number of `medium` lines fitting into the screen height.
+const LINES_PER_SCREEN: f64 = 11.0;
+const FONT_NAME: &str = "MS PGothic";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Position {
+    Naka,
+    Ue,
+    Shita,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Layout {
+    position: Position,
+    start: i64,
+    end: i64,
+    top: f64,
+    bottom: f64,
+    width: f64,
+}
+
+impl Layout {
+    /// Pixels per millisecond of a scrolling comment.
+    fn speed(&self, screen_width: f64) -> f64 {
+        (screen_width + self.width) / (self.end - self.start) as f64
+    }
+
+    fn collides(&self, other: &Layout, screen_width: f64) -> bool {
+        if self.position != other.position || self.bottom <= other.top || other.bottom <= self.top {
+            return false;
+        }
+        if self.position != Position::Naka {
+            return self.start < other.end && other.start < self.end;
+        }
+        let (prev, next) = if self.start <= other.start {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        if next.start >= prev.end {
+            return false;
+        }
+        // the tail of the previous comment must have entered the screen...
+        let tail_in = prev.start as f64 + prev.width / prev.speed(screen_width);
+        if (next.start as f64) < tail_in {
+            return true;
+        }
+        // ...and the next one must not catch up with it before it leaves
+        let next_reaches_left = next.start as f64 + screen_width / next.speed(screen_width);
+        next_reaches_left < prev.end as f64
+    }
+}
+
+/// Allocates non-overlapping lanes for comments given in display order.
+struct Lanes {
+    width: f64,
+    height: f64,
+    placed: Vec<Layout>,
+}
+
+impl Lanes {
+    fn new(width: f64, height: f64) -> Self {
+        Self {
+            width,
+            height,
+            placed: vec![],
+        }
+    }
+
+    fn place(&mut self, mut layout: Layout, seed: i64) -> Layout {
+        let start = layout.start;
+        self.placed.retain(|x| x.end > start);
+
+        let h = layout.bottom - layout.top;
+        let mut candidates: Vec<f64> = vec![0.0];
+        candidates.extend(
+            self.placed
+                .iter()
+                .filter(|x| x.position == layout.position)
+                .map(|x| x.bottom),
+        );
+        candidates.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let found = candidates.into_iter().find(|&top| {
+            let candidate = Layout {
+                top,
+                bottom: top + h,
+                ..layout
+            };
+            candidate.bottom <= self.height
+                && !self
+                    .placed
+                    .iter()
+                    .any(|x| x.collides(&candidate, self.width))
+        });
+        // the screen is full: overlap somewhere, as the original player does
+        let top = found.unwrap_or_else(|| {
+            let room = (self.height - h).max(1.0) as i64;
+            (seed.rem_euclid(room)) as f64
+        });
+        layout.top = top;
+        layout.bottom = top + h;
+        self.placed.push(layout);
+        layout
+    }
+}
+
+/// `#RRGGBB` or a niconico color command => ASS `&HBBGGRR&`
+fn parse_color(command: &str) -> Option<String> {
+    let rgb = match command {
+        "white" => "FFFFFF",
+        "red" => "FF0000",
+        "pink" => "FF8080",
+        "orange" => "FFC000",
+        "yellow" => "FFFF00",
+        "green" => "00FF00",
+        "cyan" => "00FFFF",
+        "blue" => "0000FF",
+        "purple" => "C000FF",
+        "black" => "000000",
+        "white2" | "niconicowhite" => "CCCC99",
+        "red2" | "truered" => "CC0033",
+        "pink2" => "FF33CC",
+        "orange2" | "passionorange" => "FF6600",
+        "yellow2" | "madyellow" => "999900",
+        "green2" | "elementalgreen" => "00CC66",
+        "cyan2" => "00CCCC",
+        "blue2" | "marineblue" => "3399FF",
+        "purple2" | "nobleviolet" => "6633CC",
+        "black2" => "666666",
+        x => {
+            let hex = x.strip_prefix('#')?;
+            if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                return None;
+            }
+            hex
+        }
+    };
+    let rgb = rgb.to_ascii_uppercase();
+    Some(format!("&H{}{}{}&", &rgb[4..6], &rgb[2..4], &rgb[0..2]))
+}
+
+/// 12345 ms => `0:00:12.34`
+fn format_time(ms: i64) -> String {
+    let cs = ms.max(0) / 10;
+    format!(
+        "{}:{:02}:{:02}.{:02}",
+        cs / 360000,
+        cs / 6000 % 60,
+        cs / 100 % 60,
+        cs % 100
+    )
+}
+
+/// Rough rendered width: half-width characters take half of the font size.
+fn text_width(line: &str, font_size: f64) -> f64 {
+    line.chars()
+        .map(|c| {
+            if c.is_ascii() || ('\u{ff61}'..='\u{ff9f}').contains(&c) {
+                font_size / 2.0
+            } else {
+                font_size
+            }
+        })
+        .sum()
+}
+
+/// Keeps comment bodies from being interpreted as ASS override tags.
+fn escape(body: &str) -> String {
+    body.replace('\\', "＼")
+        .replace('{', "｛")
+        .replace('}', "｝")
+        .replace("\r\n", "\n")
+        .replace('\n', "\\N")
+}
+
+/// Renders nvComment threads into an ASS subtitle file.
+pub fn render(threads: &[Thread], width: i32, height: i32) -> String {
+    let mut comments: Vec<&Comment> = threads.iter().flat_map(|x| &x.comments).collect();
+    comments.sort_by_key(|x| (x.vposMs, x.no));
+
+    let (w, h) = (width as f64, height as f64);
+    let base_size = h / LINES_PER_SCREEN;
+    let mut out = String::new();
+    let _ = write!(
+        out,
+        "[Script Info]\n\
+         ScriptType: v4.00+\n\
+         PlayResX: {width}\n\
+         PlayResY: {height}\n\
+         WrapStyle: 2\n\
+         ScaledBorderAndShadow: yes\n\
+         \n\
+         [V4+ Styles]\n\
+         Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, \
+         Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, \
+         Shadow, Alignment, MarginL, MarginR, MarginV, Encoding\n\
+         Style: Default,{FONT_NAME},{size:.0},&H00FFFFFF,&H00FFFFFF,&H00000000,&H00000000,\
+         -1,0,0,0,100,100,0,0,1,{outline:.1},0,7,0,0,0,1\n\
+         \n\
+         [Events]\n\
+         Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n",
+        size = base_size,
+        outline = (base_size / 25.0).max(1.0),
+    );
+
+    let mut lanes = Lanes::new(w, h);
+    for comment in comments {
+        let (body, vpos) = (comment.body.as_str(), comment.vposMs);
+        let commands: Vec<&str> = comment.commands.iter().map(|x| x.as_str()).collect();
+        if body.is_empty() || commands.contains(&"invisible") {
+            continue;
+        }
+
+        let position = if commands.contains(&"ue") {
+            Position::Ue
+        } else if commands.contains(&"shita") {
+            Position::Shita
+        } else {
+            Position::Naka
+        };
+        let font_size = if commands.contains(&"big") {
+            base_size * 1.44
+        } else if commands.contains(&"small") {
+            base_size * 0.64
+        } else {
+            base_size
+        };
+        let duration = commands
+            .iter()
+            .find_map(|x| x.strip_prefix('@')?.parse::<f64>().ok())
+            .map(|x| (x * 1000.0) as i64)
+            .unwrap_or(match position {
+                Position::Naka => SCROLL_DURATION_MS,
+                _ => FIXED_DURATION_MS,
+            });
+        let color = commands.iter().rev().find_map(|x| parse_color(x));
+
+        let lines: Vec<&str> = body.lines().collect();
+        let text_w = lines
+            .iter()
+            .map(|x| text_width(x, font_size))
+            .fold(0.0, f64::max);
+        let layout = lanes.place(
+            Layout {
+                position,
+                start: vpos,
+                end: vpos + duration,
+                top: 0.0,
+                bottom: font_size * lines.len().max(1) as f64,
+                width: text_w,
+            },
+            comment.no,
+        );
+
+        let mut tags = match position {
+            Position::Naka => format!(
+                "\\move({:.0},{:.0},{:.0},{:.0})",
+                w, layout.top, -text_w, layout.top
+            ),
+            Position::Ue => format!("\\an8\\pos({:.0},{:.0})", w / 2.0, layout.top),
+            Position::Shita => format!("\\an2\\pos({:.0},{:.0})", w / 2.0, h - layout.top),
+        };
+        if font_size != base_size {
+            let _ = write!(tags, "\\fs{:.0}", font_size);
+        }
+        if let Some(color) = color {
+            let _ = write!(tags, "\\c{}", color);
+            if color == "&H000000&" {
+                // keep black comments readable
+                tags.push_str("\\3c&HFFFFFF&");
+            }
+        }
+        let _ = writeln!(
+            out,
+            "Dialogue: 0,{},{},Default,,0,0,0,,{{{}}}{}",
+            format_time(layout.start),
+            format_time(layout.end),
+            tags,
+            escape(body)
+        );
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use crate::comment::ass::{format_time, parse_color, render};
+    use crate::comment::test_thread;
+    use serde_json::json;
+
+    #[test]
+    fn test_color() {
+        assert_eq!(parse_color("red"), Some("&H0000FF&".to_string()));
+        assert_eq!(parse_color("#12ab56"), Some("&H56AB12&".to_string()));
+        assert_eq!(parse_color("nobleviolet"), Some("&HCC3366&".to_string()));
+        assert_eq!(parse_color("#12ab5"), None);
+        assert_eq!(parse_color("big"), None);
+    }
+
+    #[test]
+    fn test_time() {
+        assert_eq!(format_time(0), "0:00:00.00");
+        assert_eq!(format_time(12345), "0:00:12.34");
+        assert_eq!(format_time(3723450), "1:02:03.45");
+    }
+
+    #[test]
+    fn test_render() {
+        let threads = [test_thread(
+            "main",
+            json!([
+                {"no": 1, "vposMs": 1000, "body": "first", "commands": []},
+                {"no": 2, "vposMs": 1000, "body": "second", "commands": ["red", "big"]},
+                {"no": 3, "vposMs": 2000, "body": "top {\\pos(0,0)}", "commands": ["ue"]},
+                {"no": 4, "vposMs": 2500, "body": "hidden", "commands": ["invisible"]},
+            ]),
+        )];
+        let ass = render(&threads, 1280, 720);
+        assert!(ass.contains("PlayResX: 1280\nPlayResY: 720\n"));
+
+        let dialogues: Vec<&str> = ass.lines().filter(|x| x.starts_with("Dialogue:")).collect();
+        assert_eq!(dialogues.len(), 3);
+        assert_eq!(
+            dialogues[0],
+            "Dialogue: 0,0:00:01.00,0:00:05.00,Default,,0,0,0,,{\\move(1280,0,-164,0)}first"
+        );
+        // the simultaneous comment goes into the next lane
+        assert!(dialogues[1].contains("\\move(1280,65,"));
+        assert!(dialogues[1].contains("\\fs94\\c&H0000FF&}second"));
+        assert_eq!(
+            dialogues[2],
+            "Dialogue: 0,0:00:02.00,0:00:05.00,Default,,0,0,0,,{\\an8\\pos(640,0)}top ｛＼pos(0,0)｝"
+        );
+    }
+}
@@ -0,0 +1,240 @@
+use crate::Error;
+use crate::comment::{Comment, Thread};
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// At most `max_comments` comments are kept within any `window_ms` milliseconds.
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct DensityLimit {
+    pub max_comments: usize,
+    pub window_ms: i64,
+}
+
+/// Contents of the `--comment-filter` JSON file.
+///
+/// ```json
+/// {
+///   "ng_words": ["88888"],
+///   "ng_regexes": ["^w+$"],
+///   "ng_users": ["nvc:xxxx"],
+///   "ng_commands": [["big", "red"]],
+///   "min_score": -1000,
+///   "density": {"max_comments": 30, "window_ms": 1000}
+/// }
+/// ```
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct FilterConfig {
+    pub ng_words: Vec<String>,
+    pub ng_regexes: Vec<String>,
+    pub ng_users: Vec<String>,
+    /// A comment is dropped when it has every command of one of the sets.
+    pub ng_commands: Vec<Vec<String>>,
+    pub min_score: Option<i64>,
+    pub density: Option<DensityLimit>,
+}
+
+/// Number of comments dropped by each rule.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FilterSummary {
+    pub ng_word: usize,
+    pub ng_user: usize,
+    pub ng_command: usize,
+    pub score: usize,
+    pub density: usize,
+}
+
+impl FilterSummary {
+    pub fn total(&self) -> usize {
+        self.ng_word + self.ng_user + self.ng_command + self.score + self.density
+    }
+}
+
+impl fmt::Display for FilterSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} dropped (NG word: {}, NG user: {}, NG command: {}, score: {}, density: {})",
+            self.total(),
+            self.ng_word,
+            self.ng_user,
+            self.ng_command,
+            self.score,
+            self.density
+        )
+    }
+}
+
+/// Drops spam from comment threads before they are exported.
+/// Comments of the `owner` fork are never dropped.
+#[derive(Debug)]
+pub struct CommentFilter {
+    words: Vec<String>,
+    regexes: Vec<Regex>,
+    users: HashSet<String>,
+    commands: Vec<Vec<String>>,
+    min_score: Option<i64>,
+    density: Option<DensityLimit>,
+}
+
+impl CommentFilter {
+    pub fn new(config: FilterConfig) -> Result<Self, Error> {
+        let regexes = config
+            .ng_regexes
+            .iter()
+            .map(|x| Regex::new(x))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            words: config.ng_words,
+            regexes,
+            users: config.ng_users.into_iter().collect(),
+            commands: config.ng_commands,
+            min_score: config.min_score,
+            density: config.density,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let config: FilterConfig = serde_json::from_str(&fs::read_to_string(path)?)?;
+        Self::new(config)
+    }
+
+    /// Which rule drops `comment`, if any. The density cap is applied separately.
+    fn check(&self, comment: &Comment, summary: &mut FilterSummary) -> bool {
+        let counter = if self.users.contains(&comment.userId) {
+            &mut summary.ng_user
+        } else if self.words.iter().any(|x| comment.body.contains(x.as_str()))
+            || self.regexes.iter().any(|x| x.is_match(&comment.body))
+        {
+            &mut summary.ng_word
+        } else if self
+            .commands
+            .iter()
+            .any(|set| !set.is_empty() && set.iter().all(|x| comment.commands.contains(x)))
+        {
+            &mut summary.ng_command
+        } else if self.min_score.is_some_and(|x| comment.score < x) {
+            &mut summary.score
+        } else {
+            return true;
+        };
+        *counter += 1;
+        false
+    }
+
+    pub fn apply(&self, threads: &mut [Thread]) -> FilterSummary {
+        let mut summary = FilterSummary::default();
+        for thread in threads.iter_mut().filter(|x| x.fork != "owner") {
+            thread.comments.retain(|x| self.check(x, &mut summary));
+        }
+
+        if let Some(limit) = self.density {
+            // (vpos, thread index, comment index) of every droppable comment in display order
+            let mut order: Vec<(i64, usize, usize)> = threads
+                .iter()
+                .enumerate()
+                .filter(|(_, x)| x.fork != "owner")
+                .flat_map(|(t, x)| {
+                    x.comments
+                        .iter()
+                        .enumerate()
+                        .map(move |(c, x)| (x.vposMs, t, c))
+                })
+                .collect();
+            order.sort();
+
+            let mut kept: Vec<i64> = vec![];
+            let mut dropped: HashSet<(usize, usize)> = HashSet::new();
+            for (vpos, t, c) in order {
+                let in_window = kept
+                    .iter()
+                    .rev()
+                    .take_while(|&&x| x > vpos - limit.window_ms)
+                    .count();
+                if in_window >= limit.max_comments {
+                    dropped.insert((t, c));
+                } else {
+                    kept.push(vpos);
+                }
+            }
+            summary.density = dropped.len();
+            for (t, thread) in threads.iter_mut().enumerate() {
+                let mut c = 0;
+                thread.comments.retain(|_| {
+                    c += 1;
+                    !dropped.contains(&(t, c - 1))
+                });
+            }
+        }
+        summary
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::comment::filter::{CommentFilter, FilterConfig, FilterSummary};
+    use crate::comment::test_thread;
+    use serde_json::json;
+
+    #[test]
+    fn test_filter() {
+        let config: FilterConfig = serde_json::from_value(json!({
+            "ng_words": ["spam"],
+            "ng_regexes": ["^w+$"],
+            "ng_users": ["troll"],
+            "ng_commands": [["big", "red"]],
+            "min_score": -1000,
+            "density": {"max_comments": 2, "window_ms": 1000},
+        }))
+        .unwrap();
+        let filter = CommentFilter::new(config).unwrap();
+
+        let mut threads = vec![
+            test_thread(
+                "owner",
+                json!([{"no": 1, "vposMs": 0, "body": "spam", "userId": "troll"}]),
+            ),
+            test_thread(
+                "main",
+                json!([
+                    {"no": 1, "vposMs": 0, "body": "buy spam now"},
+                    {"no": 2, "vposMs": 0, "body": "wwww"},
+                    {"no": 3, "vposMs": 0, "body": "hi", "userId": "troll"},
+                    {"no": 4, "vposMs": 0, "body": "hi", "commands": ["red", "big"]},
+                    {"no": 5, "vposMs": 0, "body": "hi", "commands": ["red"]},
+                    {"no": 6, "vposMs": 0, "body": "hi", "score": -5000},
+                    {"no": 7, "vposMs": 100, "body": "hi"},
+                    {"no": 8, "vposMs": 200, "body": "hi"},
+                    {"no": 9, "vposMs": 1100, "body": "hi"},
+                ]),
+            ),
+        ];
+        let summary = filter.apply(&mut threads);
+        assert_eq!(
+            summary,
+            FilterSummary {
+                ng_word: 2,
+                ng_user: 1,
+                ng_command: 1,
+                score: 1,
+                density: 1,
+            }
+        );
+        assert_eq!(threads[0].comments.len(), 1);
+        let kept: Vec<i64> = threads[1].comments.iter().map(|x| x.no).collect();
+        assert_eq!(kept, vec![5, 7, 9]);
+    }
+
+    #[test]
+    fn test_invalid_regex() {
+        let config = FilterConfig {
+            ng_regexes: vec!["(".to_string()],
+            ..Default::default()
+        };
+        assert!(CommentFilter::new(config).is_err());
+    }
+}
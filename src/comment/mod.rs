@@ -0,0 +1,163 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+
+pub mod ass;
+pub mod filter;
+pub mod xml;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[allow(non_snake_case)]
+pub struct Comment {
+    pub id: String,
+    pub no: i64,
+    pub vposMs: i64,
+    pub body: String,
+    pub commands: Vec<String>,
+    pub userId: String,
+    pub isPremium: bool,
+    pub score: i64,
+    pub postedAt: String,
+    pub nicoruCount: i64,
+    pub nicoruId: Option<String>,
+    pub source: Option<String>,
+    pub isMyPost: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[allow(non_snake_case)]
+pub struct Thread {
+    pub id: String,
+    pub fork: String,
+    pub commentCount: i64,
+    pub comments: Vec<Comment>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GlobalComment {
+    pub count: i64,
+}
+
+/// `data` of an nvComment `/v1/threads` response.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[allow(non_snake_case)]
+pub struct CommentData {
+    pub globalComments: Vec<GlobalComment>,
+    pub threads: Vec<Thread>,
+}
+
+/// Typed nvComment response alongside the raw JSON written out for archival.
+#[derive(Debug)]
+pub struct Comments {
+    pub data: CommentData,
+    pub raw: Value,
+}
+
+/// Merges pages of threads per thread id and fork, skipping comments whose `no`
+/// is already known.
+#[derive(Debug, Default)]
+pub struct ThreadMerger {
+    threads: Vec<Thread>,
+    /// `no` of the comments in each of `threads`
+    seen: Vec<HashSet<i64>>,
+}
+
+impl ThreadMerger {
+    /// Returns the number of comments added.
+    pub fn merge(&mut self, threads: Vec<Thread>) -> usize {
+        let mut added = 0;
+        for thread in threads {
+            let Some(i) = self
+                .threads
+                .iter()
+                .position(|x| x.id == thread.id && x.fork == thread.fork)
+            else {
+                let seen: HashSet<i64> = thread.comments.iter().map(|x| x.no).collect();
+                added += seen.len();
+                let mut thread = thread;
+                let mut known = HashSet::new();
+                thread.comments.retain(|x| known.insert(x.no));
+                self.threads.push(thread);
+                self.seen.push(seen);
+                continue;
+            };
+            let (merged, seen) = (&mut self.threads[i], &mut self.seen[i]);
+            merged.commentCount = merged.commentCount.max(thread.commentCount);
+            for comment in thread.comments {
+                if seen.insert(comment.no) {
+                    merged.comments.push(comment);
+                    added += 1;
+                }
+            }
+        }
+        added
+    }
+
+    /// The merged threads with their comments sorted by `no`.
+    pub fn into_threads(self) -> Vec<Thread> {
+        let mut threads = self.threads;
+        for thread in &mut threads {
+            thread.comments.sort_by_key(|x| x.no);
+        }
+        threads
+    }
+}
+
+/// Builds a thread for tests, filling in the comment fields a test does not care about.
+#[cfg(test)]
+pub fn test_thread(fork: &str, comments: Value) -> Thread {
+    let comments = comments
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|x| {
+            let mut comment = serde_json::json!({
+                "id": format!("id{}", x["no"]),
+                "no": 0,
+                "vposMs": 0,
+                "body": "",
+                "commands": [],
+                "userId": "",
+                "isPremium": false,
+                "score": 0,
+                "postedAt": "2007-03-06T00:33:00+09:00",
+                "nicoruCount": 0,
+            });
+            for (k, v) in x.as_object().unwrap() {
+                comment[k] = v.clone();
+            }
+            serde_json::from_value(comment).unwrap()
+        })
+        .collect::<Vec<Comment>>();
+    Thread {
+        id: "1234".to_string(),
+        fork: fork.to_string(),
+        commentCount: comments.len() as i64,
+        comments,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn test_merge_threads() {
+        use crate::comment::{ThreadMerger, test_thread};
+        use serde_json::json;
+
+        let mut merger = ThreadMerger::default();
+        let first = vec![test_thread("main", json!([{"no": 5}, {"no": 6}]))];
+        assert_eq!(merger.merge(first), 2);
+        let older = vec![
+            test_thread("main", json!([{"no": 3}, {"no": 4}, {"no": 5}])),
+            test_thread("owner", json!([{"no": 1}])),
+        ];
+        assert_eq!(merger.merge(older), 3);
+        let again = vec![test_thread("main", json!([{"no": 3}]))];
+        assert_eq!(merger.merge(again), 0);
+
+        let threads = merger.into_threads();
+        assert_eq!(threads.len(), 2);
+        let nos: Vec<i64> = threads[0].comments.iter().map(|x| x.no).collect();
+        assert_eq!(nos, [3, 4, 5, 6]);
+    }
+}
@@ -0,0 +1,122 @@
+use crate::comment::Thread;
+use std::fmt::Write;
+
+/// Escapes text for use in XML content and attribute values.
+pub fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            // not allowed in XML 1.0 at all
+            c if (c as u32) < 0x20 && !matches!(c, '\t' | '\n' | '\r') => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// `fork` attribute of the legacy format: main thread has none.
+fn fork_number(fork: &str) -> Option<&'static str> {
+    match fork {
+        "owner" => Some("1"),
+        "easy" => Some("2"),
+        _ => None,
+    }
+}
+
+/// Renders nvComment threads into the classic `<packet><chat ...>` format.
+pub fn render(threads: &[Thread]) -> String {
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<packet>\n");
+
+    for thread in threads {
+        let _ = write!(
+            out,
+            "  <thread resultcode=\"0\" thread=\"{}\" last_res=\"{}\"",
+            escape(&thread.id),
+            thread.commentCount
+        );
+        if let Some(fork) = fork_number(&thread.fork) {
+            let _ = write!(out, " fork=\"{}\"", fork);
+        }
+        out.push_str("/>\n");
+    }
+
+    for thread in threads {
+        let fork = fork_number(&thread.fork);
+        for comment in &thread.comments {
+            let _ = write!(
+                out,
+                "  <chat thread=\"{}\" no=\"{}\" vpos=\"{}\"",
+                escape(&thread.id),
+                comment.no,
+                comment.vposMs / 10
+            );
+            if let Ok(date) = chrono::DateTime::parse_from_rfc3339(&comment.postedAt) {
+                let _ = write!(
+                    out,
+                    " date=\"{}\" date_usec=\"{}\"",
+                    date.timestamp(),
+                    date.timestamp_subsec_micros()
+                );
+            }
+            if !comment.commands.is_empty() {
+                let _ = write!(out, " mail=\"{}\"", escape(&comment.commands.join(" ")));
+            }
+            if !comment.userId.is_empty() {
+                let _ = write!(out, " user_id=\"{}\"", escape(&comment.userId));
+            }
+            if comment.isPremium {
+                out.push_str(" premium=\"1\"");
+            }
+            if let Some(fork) = fork {
+                let _ = write!(out, " fork=\"{}\"", fork);
+            }
+            let _ = writeln!(out, ">{}</chat>", escape(&comment.body));
+        }
+    }
+    out.push_str("</packet>\n");
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use crate::comment::test_thread;
+    use crate::comment::xml::render;
+    use serde_json::json;
+
+    #[test]
+    fn test_render() {
+        let threads = [
+            test_thread(
+                "owner",
+                json!([{
+                    "no": 1, "vposMs": 1230, "body": "歌詞 <&>", "commands": ["ue", "red"],
+                    "userId": "owner", "isPremium": true, "postedAt": "2007-03-06T00:33:00+09:00"
+                }]),
+            ),
+            test_thread(
+                "main",
+                json!([{
+                    "no": 2, "vposMs": 4567, "body": "\"wwww\"", "commands": [],
+                    "userId": "abc", "isPremium": false, "postedAt": "2007-03-06T00:34:00.5+09:00"
+                }]),
+            ),
+        ];
+        let xml = render(&threads);
+        assert_eq!(
+            xml,
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<packet>\n\
+             \x20 <thread resultcode=\"0\" thread=\"1234\" last_res=\"1\" fork=\"1\"/>\n\
+             \x20 <thread resultcode=\"0\" thread=\"1234\" last_res=\"1\"/>\n\
+             \x20 <chat thread=\"1234\" no=\"1\" vpos=\"123\" date=\"1173108780\" date_usec=\"0\" \
+             mail=\"ue red\" user_id=\"owner\" premium=\"1\" fork=\"1\">歌詞 &lt;&amp;&gt;</chat>\n\
+             \x20 <chat thread=\"1234\" no=\"2\" vpos=\"456\" date=\"1173108840\" date_usec=\"500000\" \
+             user_id=\"abc\">&quot;wwww&quot;</chat>\n\
+             </packet>\n"
+        );
+    }
+}
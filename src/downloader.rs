@@ -1,44 +1,63 @@
+use crate::http::HttpClient;
+use crate::manifest::Manifest;
 use crate::{Error, UA_STRING};
 use aes::cipher::{BlockDecryptMut, KeyIvInit, block_padding::Pkcs7};
-use futures_util::StreamExt;
+use futures_util::{StreamExt, stream};
 use reqwest::header::{ORIGIN, REFERER, USER_AGENT};
-use reqwest::{Client, Response};
+use reqwest::{RequestBuilder, Response, StatusCode};
 use std::io::prelude::*;
 use std::path::Path;
-use std::sync::Arc;
-use tokio::time::{Duration, sleep};
+use std::sync::{Arc, Mutex as StdMutex};
+use tokio::sync::Mutex;
+use tokio::time::{Duration, Instant, sleep_until};
 
 pub struct NicoVideoDownloader {
-    client: Arc<Client>,
+    client: Arc<HttpClient>,
+    jobs: usize,
+    interval: Duration,
+    next_request: Mutex<Instant>,
+}
+
+/// Signed playlist/segment/key URLs are rejected once the access right expires.
+fn check_access(res: Response) -> Result<Response, Error> {
+    if matches!(
+        res.status(),
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN | StatusCode::GONE
+    ) {
+        return Err(Error::AccessExpired);
+    }
+    Ok(res.error_for_status()?)
 }
 
 fn url_to_filename<'a>(url: &'a str, extension: &'a str) -> &'a str {
     let path = url.split_once('?').unwrap().0;
-    path.split('/')
-        .filter(|x| x.ends_with(&extension))
-        .next_back()
-        .unwrap()
+    path.split('/').rfind(|x| x.ends_with(&extension)).unwrap()
 }
 
 impl NicoVideoDownloader {
-    pub fn new(client: Arc<Client>) -> NicoVideoDownloader {
-        Self { client }
+    pub fn new(client: Arc<HttpClient>, jobs: usize, interval: Duration) -> NicoVideoDownloader {
+        Self {
+            client,
+            jobs: jobs.max(1),
+            interval,
+            next_request: Mutex::new(Instant::now()),
+        }
     }
 
     async fn download_m3u8(&self, m3u8_url: &str) -> Result<String, Error> {
-        Ok(self.get(m3u8_url).await?.text().await?.to_string())
+        self.client
+            .fetch(self.get(m3u8_url), |res| async move {
+                Ok(check_access(res)?.text().await?)
+            })
+            .await
     }
 
     async fn download_raw(&self, url: &str) -> Result<Vec<u8>, Error> {
-        let mut stream = self.get(url).await?.bytes_stream();
-
-        let mut v: Vec<u8> = vec![];
-
-        while let Some(item) = stream.next().await {
-            v.extend_from_slice(&item.unwrap());
-        }
-
-        Ok(v)
+        self.client
+            .fetch(self.get(url), |res| async move {
+                Ok(check_access(res)?.bytes().await?.to_vec())
+            })
+            .await
     }
 
     async fn download_and_decrypt(
@@ -47,7 +66,7 @@ impl NicoVideoDownloader {
         file: &Path,
         key: &[u8],
         iv: &[u8],
-    ) -> Result<(), Error> {
+    ) -> Result<Vec<u8>, Error> {
         type Aes128CbcDec = cbc::Decryptor<aes::Aes128>;
         let ciphertext = self.download_raw(url).await?;
         let plaintext = Aes128CbcDec::new(key.into(), iv.into())
@@ -57,30 +76,23 @@ impl NicoVideoDownloader {
         let mut f = std::fs::File::create(file)?;
         f.write_all(&plaintext)?;
 
-        Ok(())
+        Ok(plaintext)
     }
 
-    async fn download_into_file(&self, url: &str, file: &Path) -> Result<(), Error> {
+    async fn download_into_file(&self, url: &str, file: &Path) -> Result<Vec<u8>, Error> {
+        let v = self.download_raw(url).await?;
         let mut f = std::fs::File::create(file)?;
-        let mut stream = self.get(url).await?.bytes_stream();
+        f.write_all(&v)?;
 
-        while let Some(item) = stream.next().await {
-            f.write_all(&item.unwrap())?;
-        }
-
-        Ok(())
+        Ok(v)
     }
 
-    async fn get(&self, url: &str) -> Result<Response, Error> {
-        let res = self
-            .client
+    fn get(&self, url: &str) -> RequestBuilder {
+        self.client
             .get(url)
             .header(REFERER, "https://www.nicovideo.jp")
             .header(ORIGIN, "https://www.nicovideo.jp")
             .header(USER_AGENT, UA_STRING)
-            .send()
-            .await?;
-        Ok(res)
     }
 
     async fn download_media_playlist(
@@ -88,6 +100,7 @@ impl NicoVideoDownloader {
         playlist: &mut m3u8_rs::MediaPlaylist,
         temp_dir: &Path,
         extension: &str,
+        manifest: &StdMutex<Manifest>,
     ) -> Result<(), Error> {
         let newline: &str = if !crate::is_debug() { "\r" } else { "\n" };
 
@@ -107,38 +120,93 @@ impl NicoVideoDownloader {
         println!("IV = {:?}", iv_bytes);
 
         for segment in &mut playlist.segments {
-            sleep(Duration::from_millis(250)).await;
             if let Some(map) = &segment.map {
                 let map_url = &map.uri;
-                let map_file = Path::new(url_to_filename(map_url, extension));
-                self.download_into_file(map_url, temp_dir.join(map_file).as_path())
-                    .await?;
-                segment.map.as_mut().unwrap().uri = map_file.to_str().unwrap().to_string();
+                let map_file = url_to_filename(map_url, extension).to_string();
+                if !manifest.lock().unwrap().is_complete(&map_file) {
+                    self.wait_turn().await;
+                    let v = self
+                        .download_into_file(map_url, &temp_dir.join(&map_file))
+                        .await?;
+                    manifest.lock().unwrap().record(&map_file, &v)?;
+                }
+                segment.map.as_mut().unwrap().uri = map_file;
             }
+        }
 
-            let filename = Path::new(url_to_filename(&segment.uri, extension));
-            print!("{}{}", filename.to_str().unwrap(), newline);
+        let total = playlist.segments.len();
+        let mut done = 0;
+        let mut results = stream::iter(playlist.segments.iter_mut())
+            .map(|segment| {
+                let (key_bytes, iv_bytes) = (&key_bytes, &iv_bytes);
+                async move {
+                    let filename = url_to_filename(&segment.uri, extension).to_string();
+                    let filepath = temp_dir.join(&filename);
+                    if !manifest.lock().unwrap().is_complete(&filename) {
+                        self.wait_turn().await;
+                        let plaintext = self
+                            .download_and_decrypt(&segment.uri, &filepath, key_bytes, iv_bytes)
+                            .await?;
+                        manifest.lock().unwrap().record(&filename, &plaintext)?;
+                    }
+                    segment.uri = filename;
+                    Ok::<_, Error>(filepath)
+                }
+            })
+            .buffer_unordered(self.jobs);
+        while let Some(result) = results.next().await {
+            let filepath = result?;
+            done += 1;
+            print!(
+                "[{}/{}] {}{}",
+                done,
+                total,
+                filepath.file_name().unwrap().to_str().unwrap(),
+                newline
+            );
             std::io::stdout().flush().unwrap();
-
-            let filepath = temp_dir.join(filename);
-            self.download_and_decrypt(&segment.uri, filepath.as_path(), &key_bytes, &iv_bytes)
-                .await?;
-            segment.uri = filename.to_str().unwrap().to_string();
         }
         println!();
 
         Ok(())
     }
 
+    /// Spaces out requests by `interval` regardless of how many workers are running.
+    async fn wait_turn(&self) {
+        let mut next_request = self.next_request.lock().await;
+        sleep_until(*next_request).await;
+        *next_request = Instant::now() + self.interval;
+    }
+
     pub async fn download_playlist(
         &self,
         m3u8_url: String,
         temp_dir: &Path,
+        video_height: i32,
     ) -> Result<String, Error> {
         let master_m3u8 = self.download_m3u8(&m3u8_url).await?;
         let mut master_m3u8 = m3u8_rs::parse_master_playlist(&master_m3u8.into_bytes())
             .unwrap()
             .1;
+        // only the requested output is expected, but don't rely on the order
+        let variant_index = master_m3u8
+            .variants
+            .iter()
+            .position(|x| {
+                x.resolution
+                    .is_some_and(|r| r.height == video_height as u64)
+            })
+            .unwrap_or(0);
+        master_m3u8.variants.swap(0, variant_index);
+        master_m3u8.variants.truncate(1);
+        let alternative_index = master_m3u8
+            .alternatives
+            .iter()
+            .position(|x| Some(&x.group_id) == master_m3u8.variants[0].audio.as_ref())
+            .unwrap_or(0);
+        master_m3u8.alternatives.swap(0, alternative_index);
+        master_m3u8.alternatives.truncate(1);
+
         let video_info = &master_m3u8.variants[0];
         let audio_info = &master_m3u8.alternatives[0];
         let video_resolution = video_info.resolution.unwrap();
@@ -160,12 +228,14 @@ impl NicoVideoDownloader {
         .unwrap()
         .1;
 
+        let manifest = StdMutex::new(Manifest::load(temp_dir)?);
+
         println!("\n[+] Video");
-        self.download_media_playlist(&mut video_m3u8, temp_dir, "cmfv")
+        self.download_media_playlist(&mut video_m3u8, temp_dir, "cmfv", &manifest)
             .await?;
 
         println!("\n[+] Audio");
-        self.download_media_playlist(&mut audio_m3u8, temp_dir, "cmfa")
+        self.download_media_playlist(&mut audio_m3u8, temp_dir, "cmfa", &manifest)
             .await?;
 
         {
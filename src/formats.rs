@@ -0,0 +1,143 @@
+use crate::api_data::{ApiData, DomandAudio, DomandVideo};
+use crate::quality::QualitySelector;
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+#[allow(non_snake_case)]
+pub struct Formats<'a> {
+    pub id: &'a str,
+    pub title: &'a str,
+    pub videos: &'a [DomandVideo],
+    pub audios: &'a [DomandAudio],
+    pub isStoryboardAvailable: bool,
+    pub hasDelivery: bool,
+    pub hasLegacyDelivery: bool,
+}
+
+impl Formats<'_> {
+    pub fn new(api_data: &ApiData) -> Formats<'_> {
+        let media = &api_data.media;
+        let domand = media.domand.as_ref();
+        Formats {
+            id: &api_data.video.id,
+            title: &api_data.video.title,
+            videos: domand.map(|x| &x.videos[..]).unwrap_or_default(),
+            audios: domand.map(|x| &x.audios[..]).unwrap_or_default(),
+            isStoryboardAvailable: domand.is_some_and(|x| x.isStoryboardAvailable),
+            hasDelivery: !media.delivery.is_null(),
+            hasLegacyDelivery: media.deliveryLegacy.as_ref().is_some_and(|x| !x.is_null()),
+        }
+    }
+
+    /// Prints every domand option, marking the ones `selector` would pick.
+    pub fn print_table(&self, selector: &QualitySelector) {
+        let selected = selector.select_video(self.videos).map(|video| {
+            (
+                video.id.as_str(),
+                selector
+                    .select_audio(self.audios, video)
+                    .map(|x| x.id.as_str()),
+            )
+        });
+        let mark = |selected: bool| if selected { "*" } else { " " };
+
+        println!("{} {}", self.id, self.title);
+        println!(
+            "   {:<28} {:>6} {:>10} {:>10} {:>9} {:>7}",
+            "VIDEO", "LABEL", "RESOLUTION", "BITRATE", "AVAILABLE", "MAX_AQL"
+        );
+        for v in self.videos {
+            println!(
+                " {} {:<28} {:>6} {:>10} {:>5} kbps {:>9} {:>7}",
+                mark(selected.is_some_and(|(x, _)| x == v.id)),
+                v.id,
+                v.label,
+                format!("{}x{}", v.width, v.height),
+                v.bitRate / 1000,
+                if v.isAvailable { "yes" } else { "no" },
+                v.recommendedHighestAudioQualityLevel
+            );
+        }
+        println!(
+            "   {:<28} {:>7} {:>10} {:>10} {:>9} {:>10} {:>9}",
+            "AUDIO", "QUALITY", "SAMPLING", "BITRATE", "AVAILABLE", "LOUDNESS", "TRUE_PEAK"
+        );
+        for a in self.audios {
+            println!(
+                " {} {:<28} {:>7} {:>7} Hz {:>5} kbps {:>9} {:>6.1} LUFS {:>6.1} dB",
+                mark(selected.is_some_and(|(_, x)| x == Some(a.id.as_str()))),
+                a.id,
+                a.qualityLevel,
+                a.samplingRate,
+                a.bitRate / 1000,
+                if a.isAvailable { "yes" } else { "no" },
+                a.integratedLoudness,
+                a.truePeak
+            );
+        }
+        println!(
+            "Storyboard: {}, DMC delivery: {}, Legacy delivery: {}",
+            if self.isStoryboardAvailable {
+                "yes"
+            } else {
+                "no"
+            },
+            if self.hasDelivery { "yes" } else { "no" },
+            if self.hasLegacyDelivery { "yes" } else { "no" },
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn test_serialize() {
+        use crate::api_data::{DomandAudio, DomandVideo};
+        use crate::formats::Formats;
+        use serde_json::json;
+
+        let video = json!({
+            "id": "video-h264-720p",
+            "isAvailable": true,
+            "label": "720p",
+            "bitRate": 2000000,
+            "width": 1280,
+            "height": 720,
+            "qualityLevel": 4,
+            "recommendedHighestAudioQualityLevel": 2,
+        });
+        let audio = json!({
+            "id": "audio-aac-192kbps",
+            "isAvailable": true,
+            "bitRate": 192000,
+            "samplingRate": 48000,
+            "integratedLoudness": -16.5,
+            "truePeak": -1.5,
+            "qualityLevel": 2,
+            "loudnessCollection": [],
+        });
+        let videos: Vec<DomandVideo> = vec![serde_json::from_value(video.clone()).unwrap()];
+        let audios: Vec<DomandAudio> = vec![serde_json::from_value(audio.clone()).unwrap()];
+        let formats = Formats {
+            id: "sm9",
+            title: "title",
+            videos: &videos,
+            audios: &audios,
+            isStoryboardAvailable: true,
+            hasDelivery: false,
+            hasLegacyDelivery: false,
+        };
+        assert_eq!(
+            serde_json::to_value(&formats).unwrap(),
+            json!({
+                "id": "sm9",
+                "title": "title",
+                "videos": [video],
+                "audios": [audio],
+                "isStoryboardAvailable": true,
+                "hasDelivery": false,
+                "hasLegacyDelivery": false,
+            })
+        );
+    }
+}
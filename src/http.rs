@@ -0,0 +1,203 @@
+use crate::Error;
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Client, IntoUrl, RequestBuilder, Response, StatusCode};
+use std::error::Error as _;
+use std::future::Future;
+use std::io;
+use tokio::time::{Duration, sleep};
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Exponential backoff with "equal jitter": somewhere in [delay / 2, delay].
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+        let half = delay / 2;
+        half + half.mul_f64(rand::random::<f64>())
+    }
+}
+
+/// `reqwest::Client` which retries transient failures according to a `RetryPolicy`.
+#[derive(Debug)]
+pub struct HttpClient {
+    client: Client,
+    retry: RetryPolicy,
+}
+
+impl HttpClient {
+    pub fn new(client: Client, retry: RetryPolicy) -> HttpClient {
+        Self { client, retry }
+    }
+
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry
+    }
+
+    pub fn get<U: IntoUrl>(&self, url: U) -> RequestBuilder {
+        self.client.get(url)
+    }
+
+    pub fn post<U: IntoUrl>(&self, url: U) -> RequestBuilder {
+        self.client.post(url)
+    }
+
+    /// Sends the request, retrying on 429/5xx and on connection failures. A 429/5xx which
+    /// persists after the retries is returned as an error.
+    pub async fn send(&self, request: RequestBuilder) -> Result<Response, Error> {
+        let mut attempt = 0;
+        loop {
+            let req = request
+                .try_clone()
+                .expect("streaming request bodies can not be retried");
+            let (wait, reason) = match req.send().await {
+                Ok(res) if is_retryable_status(res.status()) && attempt < self.retry.retries => {
+                    let wait = retry_after(&res).unwrap_or_else(|| self.retry.backoff(attempt));
+                    (wait, res.status().to_string())
+                }
+                // retries are exhausted: the caller must not mistake the error page for content
+                Ok(res) if is_retryable_status(res.status()) => {
+                    log!("[-] Request failed ({}), giving up", res.status());
+                    return Err(res.error_for_status().unwrap_err().into());
+                }
+                Ok(res) => return Ok(res),
+                Err(e) if is_retryable_error(&e) && attempt < self.retry.retries => {
+                    (self.retry.backoff(attempt), e.to_string())
+                }
+                Err(e) => return Err(e.into()),
+            };
+            attempt += 1;
+            self.pause(attempt, wait, &reason).await;
+        }
+    }
+
+    /// Sends the request and reads the response with `read`, sending the request again when
+    /// the connection breaks while the body is being read.
+    pub async fn fetch<T, F, Fut>(&self, request: RequestBuilder, read: F) -> Result<T, Error>
+    where
+        F: Fn(Response) -> Fut,
+        Fut: Future<Output = Result<T, Error>>,
+    {
+        let mut attempt = 0;
+        loop {
+            let req = request
+                .try_clone()
+                .expect("streaming request bodies can not be retried");
+            match read(self.send(req).await?).await {
+                Err(Error::ReqwestError(e))
+                    if (e.is_body() || is_retryable_error(&e)) && attempt < self.retry.retries =>
+                {
+                    let wait = self.retry.backoff(attempt);
+                    attempt += 1;
+                    self.pause(attempt, wait, &e.to_string()).await;
+                }
+                x => return x,
+            }
+        }
+    }
+
+    pub async fn send_text(&self, request: RequestBuilder) -> Result<String, Error> {
+        self.fetch(request, |res| async move { Ok(res.text().await?) })
+            .await
+    }
+
+    async fn pause(&self, attempt: u32, wait: Duration, reason: &str) {
+        log!(
+            "[-] Request failed ({}), retry {}/{} in {:.1}s",
+            reason,
+            attempt,
+            self.retry.retries,
+            wait.as_secs_f64()
+        );
+        sleep(wait).await;
+    }
+}
+
+fn is_retryable_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+fn is_retryable_error(err: &reqwest::Error) -> bool {
+    if err.is_connect() || err.is_timeout() {
+        return true;
+    }
+    let mut source = err.source();
+    while let Some(e) = source {
+        if let Some(e) = e.downcast_ref::<io::Error>() {
+            return matches!(
+                e.kind(),
+                io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::BrokenPipe
+                    | io::ErrorKind::UnexpectedEof
+            );
+        }
+        source = e.source();
+    }
+    false
+}
+
+/// `Retry-After` is either delay-seconds or an HTTP-date.
+fn retry_after(res: &Response) -> Option<Duration> {
+    let value = res.headers().get(RETRY_AFTER)?.to_str().ok()?;
+    parse_retry_after(value, chrono::Utc::now())
+}
+
+fn parse_retry_after(value: &str, now: chrono::DateTime<chrono::Utc>) -> Option<Duration> {
+    if let Ok(secs) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value.trim()).ok()?;
+    (date.with_timezone(&chrono::Utc) - now).to_std().ok()
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn test_retry_after() {
+        use crate::http::parse_retry_after;
+        use tokio::time::Duration;
+
+        let now = chrono::DateTime::parse_from_rfc3339("2015-10-21T07:28:00Z")
+            .unwrap()
+            .with_timezone(&chrono::Utc);
+        assert_eq!(
+            parse_retry_after("120", now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:30 GMT", now),
+            Some(Duration::from_secs(30))
+        );
+        // a date in the past means "retry now" and falls back to the backoff
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:27:00 GMT", now),
+            None
+        );
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+
+    #[test]
+    fn test_backoff() {
+        use crate::http::RetryPolicy;
+        use tokio::time::Duration;
+
+        let policy = RetryPolicy {
+            retries: 5,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(10),
+        };
+        for (attempt, max) in [(0, 1), (1, 2), (2, 4), (3, 8), (4, 10), (30, 10)] {
+            let wait = policy.backoff(attempt);
+            let max = Duration::from_secs(max);
+            assert!(max / 2 <= wait && wait <= max, "{:?} {:?}", wait, max);
+        }
+    }
+}
@@ -1,20 +1,58 @@
-use crate::api_data::ApiData;
+use crate::api_data::{ApiData, DomandVideo};
+use crate::archive::Archive;
+use crate::cli::{Account, Cli, Command, CommentFormat, Container, Options};
+use crate::comment::Thread;
+use crate::comment::filter::CommentFilter;
 use crate::downloader::NicoVideoDownloader;
+use crate::formats::Formats;
+use crate::mylist::Mylist;
 use crate::nicovideo::NicoVideo;
+use crate::overwrite::OverwritePolicy;
+use crate::seiga::SeigaDownloader;
+use crate::target::Target;
+use crate::template::{OutputKind, Vars};
+use clap::Parser;
 use ffmpeg_cli::{FfmpegBuilder, Parameter as FFParam};
 use futures_util::{StreamExt, future::ready};
 use std::env;
 use std::fmt;
 use std::fs;
 use std::io::Write;
-use std::path::Path;
-use std::process;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// `println!` for progress and diagnostics, which go to stderr instead while stdout carries
+/// machine-readable output.
+macro_rules! log {
+    ($($arg:tt)*) => {
+        if $crate::is_stdout_data() {
+            eprintln!($($arg)*)
+        } else {
+            println!($($arg)*)
+        }
+    };
+}
 
 mod api_data;
+mod archive;
+mod channel;
+mod cli;
+mod comment;
 mod downloader;
+mod formats;
+mod http;
+mod manifest;
+mod mylist;
+mod nfo;
 mod nicovideo;
+mod overwrite;
+mod quality;
 mod seiga;
 mod series;
+mod target;
+mod template;
+mod user;
 
 pub const UA_STRING: &str =
     "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:136.0) Gecko/20100101 Firefox/136.0";
@@ -35,8 +73,10 @@ pub enum Error {
     IOError(std::io::Error),
     FFmpegError(ffmpeg_cli::Error),
     SerdeJsonError(serde_json::Error),
+    RegexError(regex::Error),
     DownloadError,
-    LongFileNameError,
+    AccessExpired,
+    PaymentRequired,
 }
 
 impl fmt::Display for Error {
@@ -46,8 +86,10 @@ impl fmt::Display for Error {
             Error::IOError(err) => write!(f, "{}", err),
             Error::FFmpegError(err) => write!(f, "{}", err),
             Error::SerdeJsonError(err) => write!(f, "{}", err),
+            Error::RegexError(err) => write!(f, "{}", err),
             Error::DownloadError => write!(f, "DownloadError"),
-            Error::LongFileNameError => write!(f, "LongFileNameError"),
+            Error::AccessExpired => write!(f, "AccessExpired"),
+            Error::PaymentRequired => write!(f, "PaymentRequired"),
         }
     }
 }
@@ -58,163 +100,199 @@ error_impl!(ReqwestError, reqwest::Error);
 error_impl!(IOError, std::io::Error);
 error_impl!(FFmpegError, ffmpeg_cli::Error);
 error_impl!(SerdeJsonError, serde_json::Error);
+error_impl!(RegexError, regex::Error);
+
+static STDOUT_IS_DATA: AtomicBool = AtomicBool::new(false);
 
 pub fn is_debug() -> bool {
     env::var("NV_DEBUG").is_ok()
 }
 
+pub fn is_stdout_data() -> bool {
+    STDOUT_IS_DATA.load(Ordering::Relaxed)
+}
+
 #[tokio::main]
-async fn main() -> Result<(), Error> {
-    let username = env::var("NV_USERNAME").unwrap_or("dummy".to_owned());
-    let password = env::var("NV_PASSWORD").unwrap_or("dummy".to_owned());
-    let totp_secret = env::var("NV_TOTP_SECRET").ok();
-    let totp_secret = totp_secret.as_deref();
-    let cookies_path = Path::new("cookies.json");
-    let nv = NicoVideo::new(cookies_path).unwrap();
-    let mut args = env::args();
-    let prog_name = args.next().unwrap();
-
-    if args.len() == 0 {
-        println!("Usage: {} [smXXX] [smYYY] ...", prog_name);
-        return Ok(());
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(cli).await {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            log!("[-] {}", e);
+            ExitCode::FAILURE
+        }
     }
+}
 
-    if !nv.is_login().await? {
-        println!("[+] Need login");
-        nv.login(&username, &password, totp_secret).await?;
-        if !nv.is_login().await? {
-            println!("[-] Login failed");
-            process::exit(1);
-        }
+/// Returns `Ok(false)` when some of the targets failed.
+async fn run(cli: Cli) -> Result<bool, Error> {
+    // keep stdout parseable for `formats --json`
+    if matches!(cli.command, Command::Formats { json: true, .. }) {
+        STDOUT_IS_DATA.store(true, Ordering::Relaxed);
     }
-    println!("[+] Login OK");
+    let nv = NicoVideo::new(&cli.account.cookies, cli.options.retry_policy())?;
 
-    for target in args {
-        if target.starts_with("series/") {
-            download_series(&nv, target.strip_prefix("series/").unwrap()).await?;
-            continue;
-        }
-        if target.starts_with("clip/") {
-            download_seiga_clips(&nv, target.strip_prefix("clip/").unwrap()).await?;
-            continue;
-        }
-        if target.starts_with("seiga-tag#") {
-            download_seiga_tags(&nv, target.strip_prefix("seiga-tag#").unwrap()).await?;
-            continue;
-        }
-        if target.starts_with("im") {
-            download_seiga(&nv, &target).await?;
-            continue;
+    if !login(&nv, &cli.account).await? {
+        log!("[-] Login failed");
+        return Ok(false);
+    }
+    log!("[+] Login OK");
+
+    let (targets, seiga_page) = match cli.command {
+        Command::Login => return Ok(true),
+        Command::Formats { ids, json } => return show_formats(&nv, &cli.options, &ids, json).await,
+        Command::Get { targets, page } => (targets, page),
+        Command::Video { ids } => (ids.into_iter().map(Target::Video).collect(), 1),
+        Command::Series { ids } => (ids.into_iter().map(Target::Series).collect(), 1),
+        Command::Mylist { ids } => (ids.into_iter().map(Target::Mylist).collect(), 1),
+        Command::WatchLater => (vec![Target::WatchLater], 1),
+        Command::MyMylists => (vec![Target::MyMylists], 1),
+        Command::Likes => (vec![Target::Likes], 1),
+        Command::Channel { ids } => (ids.into_iter().map(Target::Channel).collect(), 1),
+        Command::User { ids } => (ids.into_iter().map(Target::User).collect(), 1),
+        Command::Seiga { ids } => (ids.into_iter().map(Target::Seiga).collect(), 1),
+        Command::Clip { ids } => (ids.into_iter().map(Target::Clip).collect(), 1),
+        Command::SeigaTag { tags, page } => {
+            (tags.into_iter().map(Target::SeigaTag).collect(), page)
         }
-        if !target.starts_with("sm") && !target.starts_with("nm") {
-            println!("Video ID must start by 'sm' or 'nm'");
-            continue;
+    };
+
+    // loaded once and appended to as items complete
+    let archive = match &cli.options.download_archive {
+        Some(path) => Some(Archive::load(path)?),
+        None => None,
+    };
+    let mut failed = 0;
+    for target in targets {
+        let result =
+            download_target(&nv, &cli.options, archive.as_ref(), &target, seiga_page).await;
+        if let Err(e) = result {
+            println!("[-] {}: {}", target, e);
+            failed += 1;
         }
+    }
 
-        println!("\n[+] {}", target);
-        download_video(&nv, target).await?;
+    if failed > 0 {
+        println!("[-] {} target(s) failed", failed);
     }
-    Ok(())
+    Ok(failed == 0)
 }
 
-async fn download_seiga(nv: &NicoVideo, seiga_id: &str) -> Result<(), Error> {
-    let sd = nv.get_seiga_downloader();
-    let seiga_dir = Path::new("seiga");
-    if !seiga_dir.exists() {
-        fs::create_dir(seiga_dir)?;
+async fn show_formats(
+    nv: &NicoVideo,
+    opts: &Options,
+    ids: &[String],
+    json: bool,
+) -> Result<bool, Error> {
+    let selector = opts.quality_selector();
+    let mut failed = 0;
+    let mut formats = vec![];
+    for id in ids {
+        let Some(api_data) = nv.get_video_api_data(id).await? else {
+            log!("[-] {} is not available", id);
+            failed += 1;
+            continue;
+        };
+        if json {
+            formats.push(serde_json::to_value(Formats::new(&api_data))?);
+        } else {
+            Formats::new(&api_data).print_table(&selector);
+            println!();
+        }
     }
-
-    let metadata_dir = seiga_dir.join("metadata");
-    if !metadata_dir.exists() {
-        fs::create_dir(metadata_dir.clone())?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&formats)?);
     }
+    Ok(failed == 0)
+}
 
-    let outfile = seiga_dir.join(format!("{}.png", seiga_id,));
-    if outfile.exists() {
-        print!(
-            "[?] '{}' is existed. overwrite? [y/N]",
-            outfile.to_str().unwrap()
-        );
-        std::io::stdout().flush()?;
-        let mut line = String::new();
-        std::io::stdin().read_line(&mut line)?;
-        line.pop();
-        if line != "y" {
-            return Ok(());
-        }
+async fn login(nv: &NicoVideo, account: &Account) -> Result<bool, Error> {
+    if nv.is_login().await? {
+        return Ok(true);
     }
+    log!("[+] Need login");
+    let (Some(username), Some(password)) = (&account.username, &account.password) else {
+        log!(
+            "[-] Username and password are required (--username/--password or NV_USERNAME/NV_PASSWORD)"
+        );
+        return Ok(false);
+    };
+    nv.login(username, password, account.totp_secret.as_deref())
+        .await?;
+    nv.is_login().await
+}
 
-    match sd.download_seiga(seiga_id).await? {
-        Some((metadata, v)) => {
-            {
-                let metafile = metadata_dir.join(format!("{seiga_id}.json"));
-                let mut sf = fs::File::create(metafile)?;
-                sf.write_all(serde_json::to_string_pretty(&metadata)?.as_bytes())?;
+async fn download_target(
+    nv: &NicoVideo,
+    opts: &Options,
+    archive: Option<&Archive>,
+    target: &Target,
+    seiga_page: i32,
+) -> Result<(), Error> {
+    match target {
+        Target::Video(id) => {
+            if !["sm", "nm", "so"].iter().any(|x| id.starts_with(x)) {
+                println!("[-] Video ID must start by 'sm', 'nm' or 'so'");
+                return Err(Error::DownloadError);
             }
-            let mut sf = fs::File::create(outfile)?;
-            sf.write_all(&v)?;
+            println!("\n[+] {}", id);
+            download_video(nv, opts, archive, id.clone(), None).await
         }
-        None => {
-            println!("[-] {seiga_id} is not found, skipping. ");
+        Target::Series(id) => download_series(nv, opts, archive, id).await,
+        Target::Mylist(id) => {
+            let mylist = nv.get_mylist(id, false).await?;
+            download_mylist(nv, opts, archive, id, mylist).await
+        }
+        Target::WatchLater => {
+            let mylist = nv.get_watch_later().await?;
+            download_mylist(nv, opts, archive, "watch-later", mylist).await
         }
+        Target::MyMylists => {
+            for id in nv.get_my_mylist_ids().await? {
+                let mylist = nv.get_mylist(&id, true).await?;
+                download_mylist(nv, opts, archive, &id, mylist).await?;
+            }
+            Ok(())
+        }
+        Target::Likes => {
+            let mylist = nv.get_likes().await?;
+            download_mylist(nv, opts, archive, "likes", mylist).await
+        }
+        Target::Channel(id) => download_channel(nv, opts, archive, id).await,
+        Target::User(id) => download_user_videos(nv, opts, archive, id).await,
+        Target::Seiga(id) => download_seiga(nv, opts, archive, id).await,
+        Target::Clip(id) => download_seiga_clips(nv, opts, archive, id).await,
+        Target::SeigaTag(tag) => download_seiga_tags(nv, opts, archive, tag, seiga_page).await,
     }
-    Ok(())
 }
 
-async fn download_seiga_tags(nv: &NicoVideo, tag: &str) -> Result<(), Error> {
+async fn download_seiga(
+    nv: &NicoVideo,
+    opts: &Options,
+    archive: Option<&Archive>,
+    seiga_id: &str,
+) -> Result<(), Error> {
     let sd = nv.get_seiga_downloader();
-    let seiga_dir = Path::new("seiga");
-    if !seiga_dir.exists() {
-        fs::create_dir(seiga_dir)?;
-    }
-
-    let tag_dir = seiga_dir.join(format!("tag_{}", sanitize_filename::sanitize(tag)));
-    if !tag_dir.exists() {
-        fs::create_dir(tag_dir.clone())?;
-    }
-
-    let metadata_dir = seiga_dir.join("metadata");
-    if !metadata_dir.exists() {
-        fs::create_dir(metadata_dir.clone())?;
-    }
+    save_seiga(&sd, opts, archive, seiga_id, "").await
+}
 
-    let mut page: i32 = match env::var("NV_SEIGA_PAGE") {
-        Ok(x) => x.parse().unwrap(),
-        Err(_) => 1,
-    };
+async fn download_seiga_tags(
+    nv: &NicoVideo,
+    opts: &Options,
+    archive: Option<&Archive>,
+    tag: &str,
+    start_page: i32,
+) -> Result<(), Error> {
+    let sd = nv.get_seiga_downloader();
+    let group = format!("tag_{}", tag);
+    let mut page = start_page;
 
     loop {
         println!("[+] Page = {page}");
         let (images, next_page) = sd.get_tags(tag, page).await?;
         for im in images {
-            let outfile = tag_dir.join(format!("{}.png", im,));
-            if outfile.exists() {
-                print!(
-                    "[?] '{}' is existed. overwrite? [y/N]",
-                    outfile.to_str().unwrap()
-                );
-                std::io::stdout().flush()?;
-                let mut line = String::new();
-                std::io::stdin().read_line(&mut line)?;
-                line.pop();
-                if line != "y" {
-                    continue;
-                }
-            }
-            match sd.download_seiga(&im).await? {
-                Some((metadata, v)) => {
-                    {
-                        let metafile = metadata_dir.join(format!("{im}.json"));
-                        let mut sf = fs::File::create(metafile)?;
-                        sf.write_all(serde_json::to_string_pretty(&metadata)?.as_bytes())?;
-                    }
-                    let mut sf = fs::File::create(outfile)?;
-                    sf.write_all(&v)?;
-                }
-                None => {
-                    println!("[-] {im} is not found, skipping. ");
-                }
-            }
+            save_seiga(&sd, opts, archive, &im, &group).await?;
         }
         if next_page.is_none() {
             break;
@@ -225,55 +303,19 @@ async fn download_seiga_tags(nv: &NicoVideo, tag: &str) -> Result<(), Error> {
     Ok(())
 }
 
-async fn download_seiga_clips(nv: &NicoVideo, clip_id: &str) -> Result<(), Error> {
+async fn download_seiga_clips(
+    nv: &NicoVideo,
+    opts: &Options,
+    archive: Option<&Archive>,
+    clip_id: &str,
+) -> Result<(), Error> {
     let sd = nv.get_seiga_downloader();
-    let seiga_dir = Path::new("seiga");
-    if !seiga_dir.exists() {
-        fs::create_dir(seiga_dir)?;
-    }
-
-    let clip_dir = seiga_dir.join(format!("clip_{clip_id}"));
-    if !clip_dir.exists() {
-        fs::create_dir(clip_dir.clone())?;
-    }
-
-    let metadata_dir = seiga_dir.join("metadata");
-    if !metadata_dir.exists() {
-        fs::create_dir(metadata_dir.clone())?;
-    }
-
+    let group = format!("clip_{clip_id}");
     let mut page = 1;
     loop {
         let (images, next_page) = sd.get_clips(clip_id, page).await?;
         for im in images {
-            let outfile = clip_dir.join(format!("{}.png", im,));
-            if outfile.exists() {
-                print!(
-                    "[?] '{}' is existed. overwrite? [y/N]",
-                    outfile.to_str().unwrap()
-                );
-                std::io::stdout().flush()?;
-                let mut line = String::new();
-                std::io::stdin().read_line(&mut line)?;
-                line.pop();
-                if line != "y" {
-                    continue;
-                }
-            }
-            match sd.download_seiga(&im).await? {
-                Some((metadata, v)) => {
-                    {
-                        let metafile = metadata_dir.join(format!("{im}.json"));
-                        let mut sf = fs::File::create(metafile)?;
-                        sf.write_all(serde_json::to_string_pretty(&metadata)?.as_bytes())?;
-                    }
-                    let mut sf = fs::File::create(outfile)?;
-                    sf.write_all(&v)?;
-                }
-                None => {
-                    println!("[-] {im} is not found, skipping. ");
-                }
-            }
+            save_seiga(&sd, opts, archive, &im, &group).await?;
         }
         if next_page.is_none() {
             break;
@@ -284,116 +326,349 @@ async fn download_seiga_clips(nv: &NicoVideo, clip_id: &str) -> Result<(), Error
     Ok(())
 }
 
-async fn download_series(nv: &NicoVideo, series_id: &str) -> Result<(), Error> {
+/// Downloads a seiga image and its metadata. `group` is the tag or clip it was found in.
+async fn save_seiga(
+    sd: &SeigaDownloader,
+    opts: &Options,
+    archive: Option<&Archive>,
+    image_id: &str,
+    group: &str,
+) -> Result<(), Error> {
+    if archive.is_some_and(|x| x.contains(image_id)) {
+        println!("[+] {} is in the download archive, skipping", image_id);
+        return Ok(());
+    }
+
+    let outputs = opts.output_templates();
+    let template = outputs.get(OutputKind::Seiga);
+    let policy = opts.overwrite_policy();
+    let mut vars = Vars {
+        id: image_id.to_string(),
+        group: group.to_string(),
+        ..Default::default()
+    };
+
+    // skip existing images without downloading them when the path is known from the id
+    let mut outfile = None;
+    if template.needs_only_id() {
+        let Some(x) = policy.resolve(&template.render(&vars, "png"))? else {
+            return Ok(());
+        };
+        outfile = Some(x);
+    }
+
+    let Some((metadata, v)) = sd.download_seiga(image_id).await? else {
+        println!("[-] {image_id} is not found, skipping. ");
+        return Ok(());
+    };
+    vars.title = metadata.title.clone();
+    vars.owner = metadata.owner_nickname.clone().unwrap_or_default();
+    vars.owner_id = metadata.owner_id.clone().unwrap_or_default();
+    vars.date = metadata.created_at();
+
+    let metafile = outputs.get(OutputKind::SeigaMetadata).render(&vars, "json");
+    write_file(&metafile, serde_json::to_string_pretty(&metadata)?)?;
+    let outfile = match outfile {
+        Some(x) => x,
+        None => match policy.resolve(&template.render(&vars, "png"))? {
+            Some(x) => x,
+            None => return Ok(()),
+        },
+    };
+    write_file(&outfile, v)?;
+
+    if let Some(archive) = archive {
+        archive.record(image_id, None, &[outfile, metafile])?;
+    }
+    Ok(())
+}
+
+async fn download_series(
+    nv: &NicoVideo,
+    opts: &Options,
+    archive: Option<&Archive>,
+    series_id: &str,
+) -> Result<(), Error> {
     let series = nv.get_series(series_id).await?;
-    {
-        let series_dir = Path::new("series");
-        if !series_dir.exists() {
-            fs::create_dir(series_dir)?;
-        }
+    let outputs = opts.output_templates();
+    let vars = Vars::series(&series);
+    write_file(
+        &outputs.get(OutputKind::Series).render(&vars, "json"),
+        serde_json::to_string_pretty(&series)?,
+    )?;
+    if opts.write_nfo {
+        let path = outputs.get(OutputKind::Tvshow).render(&vars, "nfo");
+        write_file(&path, nfo::tvshow(&series))?;
+    }
+    for (i, video_id) in series.items.into_iter().enumerate() {
+        download_listed_video(nv, opts, archive, video_id, Some(i + 1)).await?;
+    }
+    Ok(())
+}
 
-        let mut sf = fs::File::create(format!("series/{}.json", series_id))?;
-        sf.write_all(serde_json::to_string_pretty(&series)?.as_bytes())?;
+/// Writes the listing as `{id}` of the mylist output and downloads its videos.
+async fn download_mylist(
+    nv: &NicoVideo,
+    opts: &Options,
+    archive: Option<&Archive>,
+    id: &str,
+    mylist: Mylist,
+) -> Result<(), Error> {
+    let mut vars = Vars::mylist(&mylist);
+    vars.id = id.to_string();
+    let path = opts
+        .output_templates()
+        .get(OutputKind::Mylist)
+        .render(&vars, "json");
+    write_file(&path, serde_json::to_string_pretty(&mylist)?)?;
+    println!("[+] Mylist {}: {} videos", mylist.name, mylist.items.len());
+    for item in mylist.items {
+        println!("\n[+] {} {}", item.id, item.title);
+        download_listed_video(nv, opts, archive, item.id, None).await?;
     }
-    for video_id in series.items {
-        download_video(nv, video_id).await?;
-        tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+    Ok(())
+}
+
+async fn download_channel(
+    nv: &NicoVideo,
+    opts: &Options,
+    archive: Option<&Archive>,
+    channel: &str,
+) -> Result<(), Error> {
+    let videos = nv.get_channel_videos(channel).await?;
+    println!("[+] Channel {}: {} videos", channel, videos.len());
+    for video in videos {
+        println!("\n[+] {} {}", video.id, video.title);
+        download_listed_video(nv, opts, archive, video.id, None).await?;
+    }
+    Ok(())
+}
+
+async fn download_user_videos(
+    nv: &NicoVideo,
+    opts: &Options,
+    archive: Option<&Archive>,
+    user_id: &str,
+) -> Result<(), Error> {
+    let videos = nv.get_user_videos(user_id).await?;
+    println!("[+] User {}: {} videos", user_id, videos.total_count);
+    for video in videos.items {
+        println!(
+            "\n[+] {} {} ({}s, {})",
+            video.id, video.title, video.duration, video.registered_at
+        );
+        download_listed_video(nv, opts, archive, video.id, None).await?;
     }
     Ok(())
 }
 
-async fn download_video(nv: &NicoVideo, target: String) -> Result<(), Error> {
+/// Downloads a video of a series or a listing: paid-only ones are reported and skipped
+/// instead of stopping the whole listing, and requests are paced between videos.
+async fn download_listed_video(
+    nv: &NicoVideo,
+    opts: &Options,
+    archive: Option<&Archive>,
+    video_id: String,
+    series_index: Option<usize>,
+) -> Result<(), Error> {
+    match download_video(nv, opts, archive, video_id, series_index).await {
+        Err(Error::PaymentRequired) => {}
+        x => x?,
+    }
+    tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+    Ok(())
+}
+
+/// The 1-based position of a video of a series which is not downloaded as part of it.
+async fn series_position(nv: &NicoVideo, api_data: &ApiData) -> Option<usize> {
+    let series = api_data.series.as_ref()?;
+    match nv.get_series_items(&series.id.to_string()).await {
+        Ok(items) => items
+            .iter()
+            .position(|x| *x == api_data.video.id)
+            .map(|x| x + 1),
+        Err(e) => {
+            println!("[-] Series {} is not available: {}", series.id, e);
+            None
+        }
+    }
+}
+
+/// `series_index` is the 1-based position of the video when downloading a series; it is
+/// looked up for other videos of a series when the output needs it.
+async fn download_video(
+    nv: &NicoVideo,
+    opts: &Options,
+    archive: Option<&Archive>,
+    target: String,
+    series_index: Option<usize>,
+) -> Result<(), Error> {
+    if !opts.list_formats && archive.is_some_and(|x| x.contains(&target)) {
+        println!("[+] {} is in the download archive, skipping", target);
+        return Ok(());
+    }
+
     let api_data: ApiData = match nv.get_video_api_data(&target).await? {
         Some(x) => x,
-        None => return Ok(()),
+        None => {
+            println!("[-] {} is not available", target);
+            return Err(Error::DownloadError);
+        }
     };
     println!("[+] Title: {}", api_data.video.title);
 
-    let m3u8_url = nv.update_hls_cookie(&api_data, &target).await?;
+    let selector = opts.quality_selector();
+    if opts.list_formats {
+        Formats::new(&api_data).print_table(&selector);
+        return Ok(());
+    }
+    if api_data.is_payment_required() {
+        let kind = if api_data.is_channel_video() {
+            "channel video"
+        } else {
+            "video"
+        };
+        println!(
+            "[-] {} is a paid-only {}: purchase or membership is required, skipping",
+            target, kind
+        );
+        return Err(Error::PaymentRequired);
+    }
+    let Some((video, audio)) = api_data
+        .media
+        .domand
+        .as_ref()
+        .and_then(|x| selector.select(x))
+    else {
+        println!("[-] No available format matches the quality options");
+        return Err(Error::DownloadError);
+    };
+    println!(
+        "[+] Quality: {} ({}x{}), audio {} kbps",
+        video.label,
+        video.width,
+        video.height,
+        audio.bitRate / 1000
+    );
+
+    let m3u8_url = nv
+        .update_hls_cookie(&api_data, &target, video, audio)
+        .await?;
     if is_debug() {
         println!("master playlist is here: {}", &m3u8_url);
     }
 
-    let outfile = format!(
-        "{}_{}.mp4",
-        target,
-        sanitize_filename::sanitize(&api_data.video.title)
-    );
-    let outfile = Path::new(&outfile);
-    if outfile.exists() {
-        print!(
-            "[?] '{}' is existed. overwrite? [y/N]",
-            outfile.to_str().unwrap()
-        );
-        std::io::stdout().flush()?;
-        let mut line = String::new();
-        std::io::stdin().read_line(&mut line)?;
-        line.pop();
-        if line != "y" {
-            return Ok(());
-        }
+    let outputs = opts.output_templates();
+    let template = outputs.get(OutputKind::Video);
+    let mut vars = Vars::video(&api_data, series_index);
+    vars.quality = video.label.clone();
+    // the position costs a fetch of the whole series: only look it up when it is used, and
+    // after the overwrite check unless the file name depends on it
+    let index_in_name = template.uses_series_index();
+    if series_index.is_none() && index_in_name {
+        vars.series_index = series_position(nv, &api_data).await;
     }
-
-    let outfile_short = format!("{}.mp4", target);
-    let outfile_short = Path::new(&outfile_short);
-    if outfile_short.exists() {
-        print!(
-            "[?] '{}' is existed. overwrite? [y/N]",
-            outfile_short.to_str().unwrap()
-        );
-        std::io::stdout().flush()?;
-        let mut line = String::new();
-        std::io::stdin().read_line(&mut line)?;
-        line.pop();
-        if line != "y" {
-            return Ok(());
-        }
+    let ext = opts.container.extension();
+    let policy = opts.overwrite_policy();
+    let outfile = template.render(&vars, ext);
+    let Some(outfile) = policy.resolve(&outfile)? else {
+        return Ok(());
+    };
+    if series_index.is_none() && !index_in_name && opts.write_nfo {
+        vars.series_index = series_position(nv, &api_data).await;
     }
 
     println!("Downloading comments...");
-    // write-out comments
-    {
-        let comments_dir = Path::new("comments");
-        if !comments_dir.exists() {
-            fs::create_dir(comments_dir)?;
-        }
+    let ass_path = download_comments(nv, opts, &api_data, &vars, video).await?;
 
-        let comments = nv.get_comments(&api_data).await?;
-        let mut cf = fs::File::create(format!("comments/{}.json", target))?;
-        cf.write_all(serde_json::to_string_pretty(&comments["data"])?.as_bytes())?;
-    }
-
-    let temp_dir_name = format!("download_temp_{}", target);
+    // keyed by the selected formats so that resuming with other quality options does not
+    // mix segments of different renditions
+    let temp_dir_name = format!("download_temp_{}_{}+{}", target, video.id, audio.id);
     let temp_dir = Path::new(&temp_dir_name);
     if !temp_dir.exists() {
         fs::create_dir(temp_dir)?;
+    } else {
+        println!("[+] Resuming from '{}'", temp_dir_name);
     }
 
-    let downloader = nv.get_downloader();
-    let master_playlist_filename = downloader.download_playlist(m3u8_url, temp_dir).await?;
+    let downloader = nv.get_downloader(opts.jobs, opts.segment_interval());
+    let mut m3u8_url = m3u8_url;
+    let mut refreshed = 0;
+    let master_playlist_filename = loop {
+        match downloader
+            .download_playlist(m3u8_url.clone(), temp_dir, video.height)
+            .await
+        {
+            Err(Error::AccessExpired) if refreshed < 3 => {
+                // segments already in the manifest are not fetched again
+                println!("[-] HLS access right has expired, refreshing...");
+                refreshed += 1;
+                m3u8_url = nv
+                    .update_hls_cookie(&api_data, &target, video, audio)
+                    .await?;
+            }
+            x => break x?,
+        }
+    };
+
+    let thumbnail_path = temp_dir.join("thumbnail.jpg");
+    if opts.write_thumbnail || opts.embed_thumbnail {
+        println!("[+] Downloading thumbnail");
+        let thumbnail = nv.get_thumbnail(&api_data).await?;
+        fs::write(&thumbnail_path, thumbnail)?;
+    }
+    let cover = opts.embed_thumbnail.then_some(thumbnail_path.as_path());
 
     println!("\n[+] Transcode HLS stream to mp4 video");
     let input_path = &temp_dir.join(master_playlist_filename);
 
-    match convert_video(input_path, outfile).await {
-        Ok(()) => {}
-        Err(Error::LongFileNameError) => {
-            println!("[-] Filename is too long: Retry with only video id");
-            convert_video(input_path, outfile_short).await?
+    let subtitles = match (opts.embed_comments, &ass_path) {
+        (true, Some(x)) => Some(x.as_path()),
+        _ => None,
+    };
+    let tags = api_data.metadata_tags();
+    create_parent_dir(&outfile)?;
+    let mut files = vec![outfile.clone()];
+    convert_video(
+        input_path,
+        &outfile,
+        opts.container,
+        subtitles,
+        cover,
+        &tags,
+    )
+    .await?;
+
+    if let (true, Some(ass_path)) = (opts.burn_comments, &ass_path) {
+        println!("[+] Burn comments into the video");
+        let commented = outputs
+            .get(OutputKind::Video)
+            .render(&vars, &format!("commented.{}", ext));
+        if let Some(commented) = policy.resolve(&commented)? {
+            burn_comments(&outfile, ass_path, &commented).await?;
+            files.push(commented);
         }
-        Err(e) => return Err(e),
+    }
+
+    if opts.write_thumbnail {
+        let path = outputs.get(OutputKind::Thumbnail).render(&vars, "jpg");
+        files.push(write_sidecar(policy, &path, fs::read(&thumbnail_path)?)?);
+    }
+
+    if opts.write_nfo {
+        let path = outputs.get(OutputKind::Nfo).render(&vars, "nfo");
+        let nfo = nfo::video(&api_data, vars.series_index);
+        files.push(write_sidecar(policy, &path, nfo)?);
     }
 
     // write-out metadata
-    {
-        let meta_dir = Path::new("metadata");
-        if !meta_dir.exists() {
-            fs::create_dir(meta_dir)?;
-        }
+    let path = outputs.get(OutputKind::Metadata).render(&vars, "json");
+    let metadata = serde_json::to_string_pretty(&api_data)?;
+    files.push(write_sidecar(policy, &path, metadata)?);
 
-        let api_data_string = serde_json::to_string_pretty(&api_data)?;
-        let mut mdf = fs::File::create(format!("metadata/{}.json", target))?;
-        mdf.write_all(api_data_string.as_bytes())?;
+    if let Some(archive) = archive {
+        let quality = format!("{}+{}", video.id, audio.id);
+        archive.record(&target, Some(quality), &files)?;
     }
 
     // cleanup
@@ -404,18 +679,236 @@ async fn download_video(nv: &NicoVideo, target: String) -> Result<(), Error> {
     Ok(())
 }
 
-async fn convert_video(input_path: &Path, outfile: &Path) -> Result<(), Error> {
-    let newline: &str = if !is_debug() { "\r" } else { "\n" };
-    let builder = FfmpegBuilder::new()
+async fn download_comments(
+    nv: &NicoVideo,
+    opts: &Options,
+    api_data: &ApiData,
+    vars: &Vars,
+    video: &DomandVideo,
+) -> Result<Option<PathBuf>, Error> {
+    let template = opts.output_templates().get(OutputKind::Comments);
+    let policy = opts.overwrite_policy();
+
+    let comments = nv.get_comments(api_data).await?;
+    let mut threads: Vec<Thread> = comments
+        .data
+        .threads
+        .iter()
+        .filter(|x| opts.has_comment_fork(&x.fork))
+        .cloned()
+        .collect();
+    if let Some(path) = &opts.comment_filter {
+        let summary = CommentFilter::load(path)?.apply(&mut threads);
+        println!("[+] Comment filter: {}", summary);
+    }
+    for thread in &threads {
+        println!(
+            "[+] {} ({}): {} comments",
+            thread.fork,
+            thread.id,
+            thread.comments.len()
+        );
+    }
+
+    // each rendered output is written for all selected forks and, if asked, per fork
+    let mut outputs = vec![(String::new(), threads.clone())];
+    if opts.split_comment_forks {
+        for fork in &opts.comment_forks {
+            let forked: Vec<Thread> = threads
+                .iter()
+                .filter(|x| x.fork == fork.as_str())
+                .cloned()
+                .collect();
+            if !forked.is_empty() {
+                outputs.push((format!("{}.", fork.as_str()), forked));
+            }
+        }
+    }
+
+    if opts.comment_format.contains(&CommentFormat::Json) {
+        write_sidecar(
+            policy,
+            &template.render(vars, "json"),
+            serde_json::to_string_pretty(&comments.raw["data"])?,
+        )?;
+        for thread in &threads {
+            let ext = format!("{}.{}.json", thread.fork, thread.id);
+            write_sidecar(
+                policy,
+                &template.render(vars, &ext),
+                serde_json::to_string_pretty(thread)?,
+            )?;
+        }
+    }
+    let mut ass_path = None;
+    for (prefix, threads) in &outputs {
+        if opts.renders_ass() {
+            let ass = comment::ass::render(threads, video.width, video.height);
+            let path = write_sidecar(
+                policy,
+                &template.render(vars, &format!("{}ass", prefix)),
+                ass,
+            )?;
+            // the combined one is embedded or burned in
+            if prefix.is_empty() {
+                ass_path = Some(path);
+            }
+        }
+        if opts.comment_format.contains(&CommentFormat::Xml) {
+            let xml = comment::xml::render(threads);
+            write_sidecar(
+                policy,
+                &template.render(vars, &format!("{}xml", prefix)),
+                xml,
+            )?;
+        }
+    }
+
+    if opts.comment_history {
+        println!("Downloading past comments...");
+        let threads = nv.get_past_comments(api_data).await?;
+        for fork in &opts.comment_forks {
+            let forked: Vec<_> = threads.iter().filter(|x| x.fork == fork.as_str()).collect();
+            if forked.is_empty() {
+                continue;
+            }
+            println!(
+                "[+] {} (history): {} comments",
+                fork.as_str(),
+                forked.iter().map(|x| x.comments.len()).sum::<usize>()
+            );
+            let ext = format!("history.{}.json", fork.as_str());
+            write_sidecar(
+                policy,
+                &template.render(vars, &ext),
+                serde_json::to_string_pretty(&forked)?,
+            )?;
+        }
+    }
+    Ok(ass_path)
+}
+
+/// Creates the directories leading to `path` rendered from an output template.
+fn create_parent_dir(path: &Path) -> Result<(), Error> {
+    if let Some(parent) = path.parent().filter(|x| !x.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    Ok(())
+}
+
+fn write_file(path: &Path, contents: impl AsRef<[u8]>) -> Result<(), Error> {
+    create_parent_dir(path)?;
+    fs::write(path, contents)?;
+    Ok(())
+}
+
+/// Writes a file accompanying a video under the same overwrite policy as the video. Returns
+/// the path holding the contents now: the one written to, or the existing file kept.
+fn write_sidecar(
+    policy: OverwritePolicy,
+    path: &Path,
+    contents: impl AsRef<[u8]>,
+) -> Result<PathBuf, Error> {
+    let Some(resolved) = policy.resolve(path)? else {
+        return Ok(path.to_path_buf());
+    };
+    write_file(&resolved, contents)?;
+    Ok(resolved)
+}
+
+/// `subtitles` is muxed in as a soft subtitle track in the codec `container` supports,
+/// `cover` is attached as cover art and `tags` are written as container metadata.
+async fn convert_video(
+    input_path: &Path,
+    outfile: &Path,
+    container: Container,
+    subtitles: Option<&Path>,
+    cover: Option<&Path>,
+    tags: &[(&str, String)],
+) -> Result<(), Error> {
+    let tags: Vec<String> = tags.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+    let mut builder = FfmpegBuilder::new()
         // .stderr(Stdio::piped())
-        .option(FFParam::KeyValue("allowed_extensions", "ALL"))
-        .option(FFParam::KeyValue("protocol_whitelist", "file"))
-        .input(ffmpeg_cli::File::new(input_path.to_str().unwrap()))
+        .option(FFParam::Single("y"))
+        .input(
+            ffmpeg_cli::File::new(input_path.to_str().unwrap())
+                .option(FFParam::KeyValue("allowed_extensions", "ALL"))
+                .option(FFParam::KeyValue("protocol_whitelist", "file")),
+        );
+    let mut output =
+        ffmpeg_cli::File::new(outfile.to_str().unwrap()).option(FFParam::KeyValue("g", "15"));
+    // .option(FFParam::KeyValue("b:v", "16m")),
+    for tag in &tags {
+        output = output.option(FFParam::KeyValue("metadata", tag));
+    }
+    if container == Container::Mp4 {
+        // otherwise the mp4 muxer drops tags it does not know such as `purl`
+        output = output.option(FFParam::KeyValue("movflags", "+use_metadata_tags"));
+    }
+    if subtitles.is_some() || cover.is_some() {
+        output = output
+            .option(FFParam::KeyValue("map", "0:v"))
+            .option(FFParam::KeyValue("map", "0:a"));
+    }
+    if let Some(path) = subtitles {
+        builder = builder.input(ffmpeg_cli::File::new(path.to_str().unwrap()));
+        output = output
+            .option(FFParam::KeyValue("map", "1:s"))
+            .option(FFParam::KeyValue("c:s", container.subtitle_codec()))
+            .option(FFParam::KeyValue("metadata:s:s:0", "language=jpn"))
+            .option(FFParam::KeyValue(
+                "metadata:s:s:0",
+                "title=Niconico comments",
+            ));
+    }
+    if let Some(path) = cover {
+        builder = builder.input(ffmpeg_cli::File::new(path.to_str().unwrap()));
+        let input = if subtitles.is_some() { "2:v" } else { "1:v" };
+        output = output
+            .option(FFParam::KeyValue("map", input))
+            .option(FFParam::KeyValue("c:v:1", "copy"))
+            .option(FFParam::KeyValue("disposition:v:1", "attached_pic"));
+    }
+    run_ffmpeg(builder.output(output)).await
+}
+
+/// Second pass rendering the ASS comments into the video stream of `video_path`.
+async fn burn_comments(video_path: &Path, ass_path: &Path, outfile: &Path) -> Result<(), Error> {
+    let filter = format!("ass={}", escape_filter_path(ass_path));
+    let builder = FfmpegBuilder::new()
+        .option(FFParam::Single("y"))
+        .input(ffmpeg_cli::File::new(video_path.to_str().unwrap()))
         .output(
-            ffmpeg_cli::File::new(outfile.to_str().unwrap()).option(FFParam::KeyValue("g", "15")),
-            // .option(FFParam::KeyValue("b:v", "16m")),
+            ffmpeg_cli::File::new(outfile.to_str().unwrap())
+                // the first video stream, not the cover art
+                .option(FFParam::KeyValue("map", "0:v:0"))
+                .option(FFParam::KeyValue("map", "0:a"))
+                .option(FFParam::KeyValue("vf", &filter))
+                .option(FFParam::KeyValue("c:a", "copy")),
         );
+    run_ffmpeg(builder).await
+}
+
+/// Quotes a path for use as a filter option value in a filtergraph: first as the option
+/// value of the filter, then the result again as part of the filtergraph description.
+fn escape_filter_path(path: &Path) -> String {
+    let value = escape_chars(&path.to_string_lossy(), &['\\', '\'', ':']);
+    escape_chars(&value, &['\\', '\'', '[', ']', ',', ';'])
+}
 
+fn escape_chars(s: &str, special: &[char]) -> String {
+    let mut out = String::new();
+    for c in s.chars() {
+        if special.contains(&c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+async fn run_ffmpeg(builder: FfmpegBuilder<'_>) -> Result<(), Error> {
+    let newline: &str = if !is_debug() { "\r" } else { "\n" };
     let ffmpeg = builder.run().await?;
 
     ffmpeg
@@ -444,11 +937,37 @@ async fn convert_video(input_path: &Path, outfile: &Path) -> Result<(), Error> {
         );
     }
 
-    if let Some(220) = status.code() {
-        return Err(Error::LongFileNameError);
+    if !status.success() {
+        println!("[-] ffmpeg failed: {}", status);
+        return Err(Error::DownloadError);
     }
 
     println!("Done");
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn test_escape_filter_path() {
+        use crate::escape_filter_path;
+        use std::path::Path;
+
+        assert_eq!(
+            escape_filter_path(Path::new("comments/sm9.ass")),
+            "comments/sm9.ass"
+        );
+        // the example of the "Notes on filtergraph escaping" in the ffmpeg docs
+        assert_eq!(
+            escape_filter_path(Path::new(
+                "this is a 'string': may contain one, or more, special characters"
+            )),
+            r"this is a \\\'string\\\'\\: may contain one\, or more\, special characters"
+        );
+        assert_eq!(
+            escape_filter_path(Path::new(r"C:\a\[b].ass")),
+            r"C\\:\\\\a\\\\\[b\].ass"
+        );
+    }
+}
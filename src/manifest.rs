@@ -0,0 +1,104 @@
+use crate::Error;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+const MANIFEST_FILENAME: &str = "manifest.jsonl";
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+struct ManifestEntry {
+    file: String,
+    size: u64,
+    sha1: String,
+}
+
+/// Append-only record of the segments already written into a download temp dir.
+#[derive(Debug)]
+pub struct Manifest {
+    dir: PathBuf,
+    entries: HashMap<String, ManifestEntry>,
+}
+
+impl Manifest {
+    pub fn load(dir: &Path) -> Result<Manifest, Error> {
+        let mut entries = HashMap::new();
+        let path = dir.join(MANIFEST_FILENAME);
+        if path.exists() {
+            for line in BufReader::new(fs::File::open(path)?).lines() {
+                // a run killed while appending leaves a broken last line behind
+                if let Ok(entry) = serde_json::from_str::<ManifestEntry>(&line?) {
+                    entries.insert(entry.file.clone(), entry);
+                }
+            }
+        }
+        Ok(Manifest {
+            dir: dir.to_path_buf(),
+            entries,
+        })
+    }
+
+    /// Whether `file` has been recorded and is still intact on disk.
+    pub fn is_complete(&self, file: &str) -> bool {
+        let Some(entry) = self.entries.get(file) else {
+            return false;
+        };
+        match fs::metadata(self.dir.join(file)) {
+            Ok(meta) if meta.len() == entry.size => {}
+            _ => return false,
+        }
+        match fs::read(self.dir.join(file)) {
+            Ok(data) => sha1_hex(&data) == entry.sha1,
+            Err(_) => false,
+        }
+    }
+
+    pub fn record(&mut self, file: &str, data: &[u8]) -> Result<(), Error> {
+        let entry = ManifestEntry {
+            file: file.to_string(),
+            size: data.len() as u64,
+            sha1: sha1_hex(data),
+        };
+        let mut f = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.dir.join(MANIFEST_FILENAME))?;
+        writeln!(f, "{}", serde_json::to_string(&entry)?)?;
+        self.entries.insert(entry.file.clone(), entry);
+        Ok(())
+    }
+}
+
+pub fn sha1_hex(data: &[u8]) -> String {
+    hex::encode(Sha1::digest(data))
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn test_manifest() {
+        use crate::manifest::Manifest;
+        use std::fs;
+
+        let dir = std::env::temp_dir().join(format!("nv_manifest_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("1.cmfv"), b"segment 1").unwrap();
+        fs::write(dir.join("2.cmfv"), b"segment 2").unwrap();
+
+        let mut manifest = Manifest::load(&dir).unwrap();
+        assert!(!manifest.is_complete("1.cmfv"));
+        manifest.record("1.cmfv", b"segment 1").unwrap();
+        manifest.record("2.cmfv", b"segment 2").unwrap();
+
+        // truncated segment must be downloaded again
+        fs::write(dir.join("2.cmfv"), b"segm").unwrap();
+        let manifest = Manifest::load(&dir).unwrap();
+        assert!(manifest.is_complete("1.cmfv"));
+        assert!(!manifest.is_complete("2.cmfv"));
+        assert!(!manifest.is_complete("3.cmfv"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,21 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct MylistItem {
+    pub id: String,
+    pub title: String,
+    pub added_at: String,
+    /// Memo attached to the item by the owner of the mylist
+    pub memo: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Mylist {
+    pub id: i64,
+    pub owner: String,
+    pub owner_name: String,
+    pub name: String,
+    pub description: String,
+    pub is_public: bool,
+    pub items: Vec<MylistItem>,
+}
@@ -0,0 +1,166 @@
+use crate::api_data::{ApiData, strip_html};
+use crate::comment::xml::escape;
+use crate::series::Series;
+use std::fmt::Write;
+
+const HEADER: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n";
+
+fn element(out: &mut String, name: &str, value: impl std::fmt::Display) {
+    let _ = writeln!(out, "  <{name}>{}</{name}>", escape(&value.to_string()));
+}
+
+/// `2007-03-06T00:33:00+09:00` => `2007-03-06`
+fn date(rfc3339: &str) -> Option<String> {
+    let date = chrono::DateTime::parse_from_rfc3339(rfc3339).ok()?;
+    Some(date.format("%Y-%m-%d").to_string())
+}
+
+/// `episodedetails` for a video of a series (`series_index` is 1-based), `movie` otherwise.
+pub fn video(api_data: &ApiData, series_index: Option<usize>) -> String {
+    let video = &api_data.video;
+    let (root, date_element) = match (&api_data.series, series_index) {
+        (Some(_), _) | (_, Some(_)) => ("episodedetails", "aired"),
+        _ => ("movie", "premiered"),
+    };
+
+    let mut out = format!("{HEADER}<{root}>\n");
+    element(&mut out, "title", &video.title);
+    if let Some(series) = &api_data.series {
+        element(&mut out, "showtitle", &series.title);
+    }
+    if let Some(index) = series_index {
+        element(&mut out, "season", 1);
+        element(&mut out, "episode", index);
+    }
+    element(&mut out, "plot", strip_html(&video.description));
+    if let Some(date) = date(&video.registeredAt) {
+        element(&mut out, "year", &date[..4]);
+        element(&mut out, date_element, date);
+    }
+    element(&mut out, "runtime", (video.duration + 59) / 60);
+    if let Some(owner) = api_data.owner_name() {
+        element(&mut out, "director", owner);
+        element(&mut out, "studio", owner);
+    }
+    for tag in api_data.tag_names() {
+        element(&mut out, "tag", tag);
+    }
+    let _ = writeln!(
+        out,
+        "  <uniqueid type=\"niconico\" default=\"true\">{}</uniqueid>",
+        escape(&video.id)
+    );
+    element(&mut out, "thumb", video.thumbnail.largest_url());
+    let _ = writeln!(
+        out,
+        "  <niconico views=\"{}\" comments=\"{}\" mylists=\"{}\" likes=\"{}\"/>",
+        video.count.view, video.count.comment, video.count.mylist, video.count.like
+    );
+    let _ = writeln!(out, "</{root}>");
+    out
+}
+
+pub fn tvshow(series: &Series) -> String {
+    let mut out = format!("{HEADER}<tvshow>\n");
+    element(&mut out, "title", &series.title);
+    element(&mut out, "plot", &series.description);
+    if let Some(date) = date(&series.created_at) {
+        element(&mut out, "premiered", date);
+    }
+    element(&mut out, "studio", &series.owner_name);
+    let _ = writeln!(
+        out,
+        "  <uniqueid type=\"niconico\" default=\"true\">series/{}</uniqueid>",
+        series.id
+    );
+    element(&mut out, "thumb", &series.thumbnail_url);
+    out.push_str("</tvshow>\n");
+    out
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn test_episode() {
+        use crate::api_data::ApiData;
+        use crate::nfo::video;
+        use serde_json::json;
+
+        let api_data: ApiData = serde_json::from_value(json!({
+            "client": {"nicosid": "", "watchId": "sm9", "watchTrackId": ""},
+            "media": {"domand": null, "delivery": null},
+            "owner": {"id": 4, "nickname": "ren"},
+            "series": {
+                "description": "",
+                "id": 11,
+                "thumbnailUrl": "",
+                "title": "<series>",
+                "video": {},
+            },
+            "tag": {"items": [{"name": "VOCALOID"}]},
+            "video": {
+                "count": {"view": 1, "comment": 2, "mylist": 3, "like": 4},
+                "duration": 61,
+                "description": "<b>plot</b>",
+                "id": "sm9",
+                "registeredAt": "2007-03-06T00:33:00+09:00",
+                "thumbnail": {"url": "https://example.com/9.jpg"},
+                "title": "title",
+            },
+        }))
+        .unwrap();
+        assert_eq!(
+            video(&api_data, Some(3)),
+            "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<episodedetails>\n\
+             \x20 <title>title</title>\n\
+             \x20 <showtitle>&lt;series&gt;</showtitle>\n\
+             \x20 <season>1</season>\n\
+             \x20 <episode>3</episode>\n\
+             \x20 <plot>plot</plot>\n\
+             \x20 <year>2007</year>\n\
+             \x20 <aired>2007-03-06</aired>\n\
+             \x20 <runtime>2</runtime>\n\
+             \x20 <director>ren</director>\n\
+             \x20 <studio>ren</studio>\n\
+             \x20 <tag>VOCALOID</tag>\n\
+             \x20 <uniqueid type=\"niconico\" default=\"true\">sm9</uniqueid>\n\
+             \x20 <thumb>https://example.com/9.jpg</thumb>\n\
+             \x20 <niconico views=\"1\" comments=\"2\" mylists=\"3\" likes=\"4\"/>\n\
+             </episodedetails>\n"
+        );
+        // a series video whose position is unknown is still an episode
+        assert!(video(&api_data, None).contains("<episodedetails>"));
+        assert!(!video(&api_data, None).contains("<episode>"));
+    }
+
+    #[test]
+    fn test_tvshow() {
+        use crate::nfo::tvshow;
+        use crate::series::Series;
+
+        let series = Series {
+            id: 11,
+            owner: 4,
+            owner_name: "ren & stimpy".to_string(),
+            title: "<series>".to_string(),
+            description: "desc".to_string(),
+            decorated_description_html: "desc".to_string(),
+            thumbnail_url: "https://example.com/11.jpg".to_string(),
+            is_listed: true,
+            created_at: "2020-01-02T03:04:05+09:00".to_string(),
+            updated_at: "2020-01-02T03:04:05+09:00".to_string(),
+            items: vec![],
+        };
+        assert_eq!(
+            tvshow(&series),
+            "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<tvshow>\n\
+             \x20 <title>&lt;series&gt;</title>\n\
+             \x20 <plot>desc</plot>\n\
+             \x20 <premiered>2020-01-02</premiered>\n\
+             \x20 <studio>ren &amp; stimpy</studio>\n\
+             \x20 <uniqueid type=\"niconico\" default=\"true\">series/11</uniqueid>\n\
+             \x20 <thumb>https://example.com/11.jpg</thumb>\n\
+             </tvshow>\n"
+        );
+    }
+}
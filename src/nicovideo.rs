@@ -1,8 +1,13 @@
-use crate::api_data::ApiData;
+use crate::api_data::{ApiData, DomandAudio, DomandVideo, NvCommentTarget};
+use crate::channel::ChannelVideo;
+use crate::comment::{Comments, Thread, ThreadMerger};
+use crate::http::{HttpClient, RetryPolicy};
+use crate::mylist::{Mylist, MylistItem};
 use crate::seiga::SeigaDownloader;
+use crate::user::{UserVideo, UserVideos};
 use crate::{Error, NicoVideoDownloader, UA_STRING};
 use reqwest::header::{CONTENT_TYPE, ORIGIN, REFERER, USER_AGENT};
-use reqwest::{Client, Response};
+use reqwest::{Client, RequestBuilder, Response};
 use reqwest_cookie_store::{CookieStore, CookieStoreMutex};
 use scraper::{Html, Selector};
 use serde_json::json;
@@ -11,16 +16,17 @@ use std::io;
 use std::io::{BufReader, BufWriter};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use tokio::time::Duration;
 
 #[derive(Debug)]
 pub struct NicoVideo {
-    client: Arc<Client>,
+    client: Arc<HttpClient>,
     cookies_path: PathBuf,
     cookies: Arc<CookieStoreMutex>,
 }
 
 impl NicoVideo {
-    pub fn new(cookies_path: &Path) -> Result<NicoVideo, Error> {
+    pub fn new(cookies_path: &Path, retry: RetryPolicy) -> Result<NicoVideo, Error> {
         let cookies = {
             if !cookies_path.exists() {
                 Ok::<CookieStore, io::Error>(CookieStore::new(None))
@@ -34,12 +40,13 @@ impl NicoVideo {
         let cookies_arc = Arc::new(cookies_mutex);
 
         Ok(NicoVideo {
-            client: Arc::new(
+            client: Arc::new(HttpClient::new(
                 Client::builder()
                     .cookie_provider(Arc::clone(&cookies_arc))
                     .build()
                     .unwrap(),
-            ),
+                retry,
+            )),
             cookies_path: cookies_path.to_owned(),
             cookies: cookies_arc,
         })
@@ -54,12 +61,16 @@ impl NicoVideo {
         let form_data = vec![("mail_tel", username), ("password", password)];
         let res = self
             .client
-            .post("https://account.nicovideo.jp/login/redirector?site=niconico&next_url=%2F")
-            .header(USER_AGENT, UA_STRING)
-            .header(REFERER, "https://account.nicovideo.jp/login?site=niconico")
-            .header(reqwest::header::ACCEPT_LANGUAGE, "en-US,en;q=0.7,en;q=0.3") // for simple
-            .form(&form_data)
-            .send()
+            .send(
+                self.client
+                    .post(
+                        "https://account.nicovideo.jp/login/redirector?site=niconico&next_url=%2F",
+                    )
+                    .header(USER_AGENT, UA_STRING)
+                    .header(REFERER, "https://account.nicovideo.jp/login?site=niconico")
+                    .header(reqwest::header::ACCEPT_LANGUAGE, "en-US,en;q=0.7,en;q=0.3") // for simple
+                    .form(&form_data),
+            )
             .await?;
         let raw_html = &res.text().await?;
         if raw_html.contains("2-Step Verification") {
@@ -128,7 +139,7 @@ impl NicoVideo {
             let status = api_data["meta"]["status"].as_i64().unwrap();
 
             if status != 200 {
-                println!("Status: {}", status);
+                log!("Status: {}", status);
                 return Ok(None);
             }
 
@@ -140,20 +151,79 @@ impl NicoVideo {
         }
     }
 
-    pub async fn get_comments(&self, api_data: &ApiData) -> Result<serde_json::Value, Error> {
-        let comment = api_data.comment.as_ref().unwrap();
-        let nv_comment = comment["nvComment"].clone();
+    pub async fn get_comments(&self, api_data: &ApiData) -> Result<Comments, Error> {
+        self.get_comments_impl(api_data, None, None).await
+    }
+
+    /// Walks back each thread separately with the `when` parameter until its oldest comment
+    /// reaches the posting date, and returns every comment found, merged per thread. Owner
+    /// comments are as old as the video and would otherwise drag the cursor of the main
+    /// thread back to the posting date after the first page.
+    pub async fn get_past_comments(&self, api_data: &ApiData) -> Result<Vec<Thread>, Error> {
+        let Some(comment) = &api_data.comment else {
+            println!("[-] Comments are not available");
+            return Err(Error::DownloadError);
+        };
+        let registered_at = chrono::DateTime::parse_from_rfc3339(&api_data.video.registeredAt)
+            .map(|x| x.timestamp())
+            .unwrap_or(0);
+        let mut merger = ThreadMerger::default();
+        for target in &comment.nvComment.params.targets {
+            let mut when = chrono::Local::now().timestamp();
+            while when > registered_at {
+                let comments = self
+                    .get_comments_impl(api_data, Some(when), Some(target))
+                    .await?;
+                let page = comments.data.threads;
+                let oldest = oldest_comment(&page);
+                let added = merger.merge(page);
+                println!(
+                    "[+] Past {} comments before {}: {} new",
+                    target.fork,
+                    chrono::DateTime::from_timestamp(when, 0).unwrap_or_default(),
+                    added
+                );
+                match next_when(when, oldest, added) {
+                    Some(x) => when = x,
+                    None => break,
+                }
+                tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+            }
+        }
+        Ok(merger.into_threads())
+    }
+
+    /// `target` limits the request to one thread, all threads of the video otherwise.
+    async fn get_comments_impl(
+        &self,
+        api_data: &ApiData,
+        when: Option<i64>,
+        target: Option<&NvCommentTarget>,
+    ) -> Result<Comments, Error> {
+        let Some(comment) = &api_data.comment else {
+            println!("[-] Comments are not available");
+            return Err(Error::DownloadError);
+        };
+        let nv_comment = &comment.nvComment;
+        let targets = match target {
+            Some(x) => json!([x]),
+            None => json!(nv_comment.params.targets),
+        };
 
+        let additionals = match when {
+            Some(when) => json!({ "when": when }),
+            None => json!({}),
+        };
         let req = json! {{
-            "additionals": {},
+            "additionals": additionals,
             "params": {
-                "targets": nv_comment["params"]["targets"],
+                "targets": targets,
                 "language": "ja-jp"
             },
-            "threadKey": nv_comment["threadKey"],
+            "threadKey": nv_comment.threadKey,
         }};
         let req_json_str = serde_json::to_string(&req).unwrap();
-        let url = format!("{}/v1/threads", nv_comment["server"].as_str().unwrap());
+        let url = format!("{}/v1/threads", nv_comment.server);
 
         if crate::is_debug() {
             println!("[+] Comment Server: {}", url);
@@ -161,22 +231,31 @@ impl NicoVideo {
 
         let res = self
             .client
-            .post(url)
-            .header(REFERER, "https://www.nicovideo.jp")
-            .header(ORIGIN, "https://www.nicovideo.jp")
-            .header(USER_AGENT, UA_STRING)
-            .header(CONTENT_TYPE, "application/json")
-            .header("X-Frontend-Id", "6")
-            .header("X-Frontend-Version", "0")
-            .header("X-NicoNico-Language", "ja-jp")
-            .body(req_json_str)
-            .send()
-            .await?
-            .text()
+            .send_text(
+                self.client
+                    .post(url)
+                    .header(REFERER, "https://www.nicovideo.jp")
+                    .header(ORIGIN, "https://www.nicovideo.jp")
+                    .header(USER_AGENT, UA_STRING)
+                    .header(CONTENT_TYPE, "application/json")
+                    .header("X-Frontend-Id", "6")
+                    .header("X-Frontend-Version", "0")
+                    .header("X-NicoNico-Language", "ja-jp")
+                    .body(req_json_str),
+            )
             .await?;
 
-        let res: serde_json::Value = serde_json::from_str(&res)?;
-        Ok(res)
+        let raw: serde_json::Value = serde_json::from_str(&res)?;
+        let status_code = raw["meta"]["status"].as_i64().unwrap_or(-1);
+        if status_code != 200 {
+            println!(
+                "Error: Comment server didn't return correctly result (expected: 200, actual: {status_code}, {})",
+                raw["meta"]["errorCode"].as_str().unwrap_or_default()
+            );
+            return Err(Error::DownloadError);
+        }
+        let data = serde_json::from_value(raw["data"].clone())?;
+        Ok(Comments { data, raw })
     }
 
     pub async fn get_series(&self, series_id: &str) -> Result<crate::series::Series, Error> {
@@ -232,6 +311,26 @@ impl NicoVideo {
         }
     }
 
+    /// Only the video ids of a series, without the details which `get_series` requires.
+    pub async fn get_series_items(&self, series_id: &str) -> Result<Vec<String>, Error> {
+        let mut page = 1;
+        let mut items = vec![];
+        loop {
+            let json = self.get_series_impl(series_id, page).await?;
+            let Some(page_items) = json["data"]["items"].as_array() else {
+                println!("[-] Unexpected series response");
+                return Err(Error::DownloadError);
+            };
+            items.extend(page_items.iter().map(|x| text(&x["meta"]["id"])));
+            let total = json["data"]["totalCount"].as_i64().unwrap_or(0);
+            if page_items.is_empty() || total <= page * 100 {
+                return Ok(items);
+            }
+            page += 1;
+            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+        }
+    }
+
     async fn get_series_impl(
         &self,
         series_id: &str,
@@ -241,18 +340,211 @@ impl NicoVideo {
             "https://nvapi.nicovideo.jp/v2/series/{}?page={}&sensitiveContents=mask&pageSize=100",
             series_id, page
         );
+        self.get_nvapi(&api_url, "Series").await
+    }
+
+    /// `own` fetches one of the logged-in user's mylists, which may be private.
+    pub async fn get_mylist(&self, mylist_id: &str, own: bool) -> Result<Mylist, Error> {
+        let Ok(id) = mylist_id.parse() else {
+            println!("[-] Invalid mylist id: {}", mylist_id);
+            return Err(Error::DownloadError);
+        };
+        let mut page = 1;
+        let mut items = vec![];
+        loop {
+            let json = self.get_mylist_impl(mylist_id, page, own).await?;
+            let mylist = &json["data"]["mylist"];
+            let Some(page_items) = mylist["items"].as_array() else {
+                println!("[-] Unexpected mylist response");
+                return Err(Error::DownloadError);
+            };
+            items.extend(page_items.iter().map(|x| MylistItem {
+                id: text(&x["watchId"]),
+                title: text(&x["video"]["title"]),
+                added_at: text(&x["addedAt"]),
+                memo: text(&x["description"]),
+            }));
+            if !mylist["hasNext"].as_bool().unwrap_or(false) {
+                return Ok(Mylist {
+                    id,
+                    owner: match &mylist["owner"]["id"] {
+                        serde_json::Value::Number(x) => x.to_string(),
+                        x => text(x),
+                    },
+                    owner_name: text(&mylist["owner"]["name"]),
+                    name: text(&mylist["name"]),
+                    description: text(&mylist["description"]),
+                    is_public: mylist["isPublic"].as_bool().unwrap_or(true),
+                    items,
+                });
+            }
+            page += 1;
+            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+        }
+    }
+
+    async fn get_mylist_impl(
+        &self,
+        mylist_id: &str,
+        page: i64,
+        own: bool,
+    ) -> Result<serde_json::Value, Error> {
+        let api_url = if own {
+            format!(
+                "https://nvapi.nicovideo.jp/v1/users/me/mylists/{}?page={}&sensitiveContents=mask&pageSize=100",
+                mylist_id, page
+            )
+        } else {
+            format!(
+                "https://nvapi.nicovideo.jp/v2/mylists/{}?page={}&sensitiveContents=mask&pageSize=100",
+                mylist_id, page
+            )
+        };
+        self.get_nvapi(&api_url, "Mylist").await
+    }
+
+    /// Ids of the logged-in user's mylists.
+    pub async fn get_my_mylist_ids(&self) -> Result<Vec<String>, Error> {
+        let json = self
+            .get_nvapi(
+                "https://nvapi.nicovideo.jp/v1/users/me/mylists?sampleItemCount=0",
+                "Mylists",
+            )
+            .await?;
+        mylist_ids(&json).ok_or_else(|| {
+            println!("[-] Unexpected mylists response");
+            Error::DownloadError
+        })
+    }
+
+    /// The logged-in user's watch-later list as a mylist.
+    pub async fn get_watch_later(&self) -> Result<Mylist, Error> {
+        let mut page = 1;
+        let mut items = vec![];
+        loop {
+            let api_url = format!(
+                "https://nvapi.nicovideo.jp/v1/users/me/watch-later?sortKey=addedAt&sortOrder=desc&page={}&pageSize=100",
+                page
+            );
+            let json = self.get_nvapi(&api_url, "Watch later").await?;
+            let watch_later = &json["data"]["watchLater"];
+            let Some(page_items) = watch_later["items"].as_array() else {
+                println!("[-] Unexpected watch later response");
+                return Err(Error::DownloadError);
+            };
+            items.extend(page_items.iter().map(|x| MylistItem {
+                id: text(&x["watchId"]),
+                title: text(&x["video"]["title"]),
+                added_at: text(&x["addedAt"]),
+                memo: text(&x["memo"]),
+            }));
+            if !watch_later["hasNext"].as_bool().unwrap_or(false) {
+                return Ok(own_list("Watch later", items));
+            }
+            page += 1;
+            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+        }
+    }
+
+    /// The logged-in user's like history as a mylist, `added_at` being when it was liked.
+    pub async fn get_likes(&self) -> Result<Mylist, Error> {
+        let mut page = 1;
+        let mut items = vec![];
+        loop {
+            let api_url = format!(
+                "https://nvapi.nicovideo.jp/v1/users/me/likes/items?page={}&pageSize=100",
+                page
+            );
+            let json = self.get_nvapi(&api_url, "Likes").await?;
+            let Some(page_items) = json["data"]["items"].as_array() else {
+                println!("[-] Unexpected likes response");
+                return Err(Error::DownloadError);
+            };
+            items.extend(page_items.iter().map(|x| MylistItem {
+                id: text(&x["video"]["id"]),
+                title: text(&x["video"]["title"]),
+                added_at: text(&x["likedAt"]),
+                memo: String::new(),
+            }));
+            if !json["data"]["summary"]["hasNext"]
+                .as_bool()
+                .unwrap_or(false)
+            {
+                return Ok(own_list("Likes", items));
+            }
+            page += 1;
+            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+        }
+    }
+
+    /// Videos of a channel given by its `ch` id or slug, newest first.
+    pub async fn get_channel_videos(&self, channel: &str) -> Result<Vec<ChannelVideo>, Error> {
+        let mut page = 1;
+        let mut videos: Vec<ChannelVideo> = vec![];
+        loop {
+            let url = format!(
+                "https://ch.nicovideo.jp/{}/video?sort=f&order=d&page={}",
+                channel, page
+            );
+            let raw_html = self.get_raw_html(&url).await?;
+            let mut found = 0;
+            for video in channel_videos(&raw_html) {
+                if !videos.iter().any(|x| x.id == video.id) {
+                    videos.push(video);
+                    found += 1;
+                }
+            }
+            if found == 0 {
+                return Ok(videos);
+            }
+            page += 1;
+            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+        }
+    }
+
+    pub async fn get_user_videos(&self, user_id: &str) -> Result<UserVideos, Error> {
+        let mut page = 1;
+        let mut items: Vec<UserVideo> = vec![];
+        loop {
+            let api_url = format!(
+                "https://nvapi.nicovideo.jp/v3/users/{}/videos?sortKey=registeredAt&sortOrder=desc&sensitiveContents=mask&pageSize=100&page={}",
+                user_id, page
+            );
+            let json = self.get_nvapi(&api_url, "User videos").await?;
+            let total = json["data"]["totalCount"].as_i64().unwrap_or(0);
+            let page_items = json["data"]["items"]
+                .as_array()
+                .cloned()
+                .unwrap_or_default();
+            for item in &page_items {
+                items.push(serde_json::from_value(item["essential"].clone())?);
+            }
+            if page_items.is_empty() || items.len() as i64 >= total {
+                return Ok(UserVideos {
+                    id: user_id.to_string(),
+                    total_count: total,
+                    items,
+                });
+            }
+            page += 1;
+            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+        }
+    }
+
+    /// GET an nvapi endpoint with the headers the web frontend sends and check `meta.status`.
+    async fn get_nvapi(&self, api_url: &str, name: &str) -> Result<serde_json::Value, Error> {
         let res = self
             .client
-            .get(&api_url)
-            .header(REFERER, "https://www.nicovideo.jp")
-            .header(ORIGIN, "https://www.nicovideo.jp")
-            .header(USER_AGENT, UA_STRING)
-            .header("X-Frontend-Id", "6")
-            .header("X-Frontend-Version", "0")
-            .header("X-NicoNico-Language", "ja-jp")
-            .send()
-            .await?
-            .text()
+            .send_text(
+                self.client
+                    .get(api_url)
+                    .header(REFERER, "https://www.nicovideo.jp")
+                    .header(ORIGIN, "https://www.nicovideo.jp")
+                    .header(USER_AGENT, UA_STRING)
+                    .header("X-Frontend-Id", "6")
+                    .header("X-Frontend-Version", "0")
+                    .header("X-NicoNico-Language", "ja-jp"),
+            )
             .await?;
         let json: serde_json::Value = serde_json::from_str(res.as_str())?;
         if crate::is_debug() {
@@ -262,7 +554,7 @@ impl NicoVideo {
         let status_code = json["meta"]["status"].as_i64().unwrap_or(-1);
         if status_code != 200 {
             println!(
-                "Error: Series API didn't return correctly result (expected: 200, actual: {status_code})"
+                "Error: {name} API didn't return correctly result (expected: 200, actual: {status_code})"
             );
             return Err(Error::DownloadError);
         }
@@ -273,35 +565,19 @@ impl NicoVideo {
         &self,
         api_data: &ApiData,
         video_id: &str,
+        video: &DomandVideo,
+        audio: &DomandAudio,
     ) -> Result<String, Error> {
-        let domand = &api_data.media.domand;
+        let Some(domand) = &api_data.media.domand else {
+            return Err(Error::PaymentRequired);
+        };
         let action_track_id = &api_data.client.watchTrackId;
         let url = format!(
             "https://nvapi.nicovideo.jp/v1/watch/{}/access-rights/hls?actionTrackId={}",
             video_id, action_track_id
         );
-        let id_video_domand = {
-            let mut videos = Vec::new();
-            videos.extend(&domand.videos);
-            videos.sort_by_key(|x| x.qualityLevel);
-            &videos
-                .iter()
-                .filter(|x| x.isAvailable)
-                .next_back()
-                .unwrap()
-                .id
-        };
-        let id_audio_domand = {
-            let mut audios = Vec::new();
-            audios.extend(&domand.audios);
-            audios.sort_by_key(|x| x.qualityLevel);
-            &audios
-                .iter()
-                .filter(|x| x.isAvailable)
-                .next_back()
-                .unwrap()
-                .id
-        };
+        let id_video_domand = &video.id;
+        let id_audio_domand = &audio.id;
         let req_json = json! {{
             "outputs": [
             [id_video_domand, id_audio_domand]
@@ -310,23 +586,32 @@ impl NicoVideo {
         let req_json_str = serde_json::to_string(&req_json).unwrap();
         let res = self
             .client
-            .post(url)
-            .header(REFERER, "https://www.nicovideo.jp")
-            .header(ORIGIN, "https://www.nicovideo.jp")
-            .header(USER_AGENT, UA_STRING)
-            .header(CONTENT_TYPE, "application/json")
-            .header("X-Request-With", "https://www.nicovideo.jp")
-            .header("X-Access-Right-Key", &domand.accessRightKey)
-            .header("X-Frontend-Id", "6")
-            .header("X-Frontend-Version", "0")
-            .header("X-NicoNico-Language", "ja-jp")
-            .body(req_json_str)
-            .send()
-            .await?
-            .text()
+            .send_text(
+                self.client
+                    .post(url)
+                    .header(REFERER, "https://www.nicovideo.jp")
+                    .header(ORIGIN, "https://www.nicovideo.jp")
+                    .header(USER_AGENT, UA_STRING)
+                    .header(CONTENT_TYPE, "application/json")
+                    .header("X-Request-With", "https://www.nicovideo.jp")
+                    .header("X-Access-Right-Key", &domand.accessRightKey)
+                    .header("X-Frontend-Id", "6")
+                    .header("X-Frontend-Version", "0")
+                    .header("X-NicoNico-Language", "ja-jp")
+                    .body(req_json_str),
+            )
             .await?;
         let res: serde_json::Value = serde_json::from_str(&res)?;
-        Ok(res["data"]["contentUrl"].as_str().unwrap().to_string())
+        match res["data"]["contentUrl"].as_str() {
+            Some(x) => Ok(x.to_string()),
+            None if res["meta"]["errorCode"].as_str() == Some("PAYMENT_REQUIRED") => {
+                Err(Error::PaymentRequired)
+            }
+            None => {
+                println!("[-] HLS access right was not granted: {}", res["meta"]);
+                Err(Error::DownloadError)
+            }
+        }
     }
 
     fn save_cookie(&self) -> Result<(), io::Error> {
@@ -336,36 +621,42 @@ impl NicoVideo {
         Ok(())
     }
 
-    async fn get(&self, url: &str) -> Result<Response, Error> {
-        let res = self
-            .client
+    fn get(&self, url: &str) -> RequestBuilder {
+        self.client
             .get(url)
             .header(REFERER, "https://www.nicovideo.jp")
             .header(ORIGIN, "https://www.nicovideo.jp")
             .header(USER_AGENT, UA_STRING)
-            .send()
-            .await?;
-        Ok(res)
     }
 
     async fn get_raw_html(&self, url: &str) -> Result<String, Error> {
-        let raw_html = self.get(url).await?.text().await?;
-        Ok(raw_html)
+        self.client.send_text(self.get(url)).await
     }
 
     async fn post(&self, url: &str, data: &Vec<(&str, &str)>) -> Result<Response, Error> {
         let ret = self
             .client
-            .post(url)
-            .header(USER_AGENT, UA_STRING)
-            .form(&data)
-            .send()
+            .send(
+                self.client
+                    .post(url)
+                    .header(USER_AGENT, UA_STRING)
+                    .form(&data),
+            )
             .await?;
         Ok(ret)
     }
 
-    pub fn get_downloader(&self) -> NicoVideoDownloader {
-        NicoVideoDownloader::new(self.client.clone())
+    pub async fn get_thumbnail(&self, api_data: &ApiData) -> Result<Vec<u8>, Error> {
+        let url = api_data.video.thumbnail.largest_url();
+        self.client
+            .fetch(self.get(url), |res| async move {
+                Ok(res.error_for_status()?.bytes().await?.to_vec())
+            })
+            .await
+    }
+
+    pub fn get_downloader(&self, jobs: usize, interval: Duration) -> NicoVideoDownloader {
+        NicoVideoDownloader::new(self.client.clone(), jobs, interval)
     }
 
     pub fn get_seiga_downloader(&self) -> SeigaDownloader {
@@ -373,6 +664,80 @@ impl NicoVideo {
     }
 }
 
+/// Lists of the logged-in user are not real mylists and have no id or owner.
+fn own_list(name: &str, items: Vec<MylistItem>) -> Mylist {
+    Mylist {
+        id: 0,
+        owner: String::new(),
+        owner_name: String::new(),
+        name: name.to_string(),
+        description: String::new(),
+        is_public: false,
+        items,
+    }
+}
+
+/// Videos of a page of the channel video listing. Only the titles in the listing itself
+/// are read: the rest of the page links to rankings and recommended videos.
+fn channel_videos(raw_html: &str) -> Vec<ChannelVideo> {
+    let selector = Selector::parse("ul.items li.item .title a[href*=\"/watch/\"]").unwrap();
+    Html::parse_document(raw_html)
+        .select(&selector)
+        .filter_map(|elem| {
+            let href = elem.value().attr("href")?;
+            let id = href
+                .split("/watch/")
+                .nth(1)?
+                .split(['?', '#', '/'])
+                .next()?;
+            let title = elem
+                .value()
+                .attr("title")
+                .map(|x| x.to_string())
+                .unwrap_or_else(|| elem.text().collect::<String>().trim().to_string());
+            Some(ChannelVideo {
+                id: id.to_string(),
+                title,
+            })
+        })
+        .collect()
+}
+
+/// Ids are numbers in the API, but strings are accepted as well.
+fn mylist_ids(json: &serde_json::Value) -> Option<Vec<String>> {
+    json["data"]["mylists"]
+        .as_array()?
+        .iter()
+        .map(|x| match &x["id"] {
+            serde_json::Value::Number(x) => x.as_i64().map(|x| x.to_string()),
+            serde_json::Value::String(x) => Some(x.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn text(x: &serde_json::Value) -> String {
+    x.as_str().unwrap_or_default().to_string()
+}
+
+/// Unix time of the oldest comment in `threads`.
+fn oldest_comment(threads: &[Thread]) -> Option<i64> {
+    threads
+        .iter()
+        .flat_map(|x| &x.comments)
+        .filter_map(|x| chrono::DateTime::parse_from_rfc3339(&x.postedAt).ok())
+        .map(|x| x.timestamp())
+        .min()
+}
+
+/// The `when` cursor for the next page of a thread, `None` when the thread is exhausted.
+fn next_when(when: i64, oldest: Option<i64>, added: usize) -> Option<i64> {
+    match oldest {
+        Some(oldest) if added > 0 => Some(oldest.min(when - 1)),
+        _ => None,
+    }
+}
+
 fn compute_totp(secret: &[u8], time: u64, period: u64, t0: u64, digits: usize) -> String {
     use hmac::{Hmac, Mac};
     use sha1::Sha1;
@@ -393,6 +758,87 @@ fn compute_totp(secret: &[u8], time: u64, period: u64, t0: u64, digits: usize) -
 
 #[cfg(test)]
 mod test {
+    #[test]
+    fn test_next_when() {
+        use crate::comment::test_thread;
+        use crate::nicovideo::{next_when, oldest_comment};
+        use serde_json::json;
+
+        let owner = test_thread(
+            "owner",
+            json!([{"no": 1, "postedAt": "2007-03-06T00:33:00+09:00"}]),
+        );
+        let main = test_thread(
+            "main",
+            json!([
+                {"no": 100, "postedAt": "2024-01-02T00:00:00+09:00"},
+                {"no": 99, "postedAt": "2024-01-01T00:00:00+09:00"},
+            ]),
+        );
+        let now = chrono::DateTime::parse_from_rfc3339("2024-06-01T00:00:00+09:00")
+            .unwrap()
+            .timestamp();
+        let main_oldest = chrono::DateTime::parse_from_rfc3339("2024-01-01T00:00:00+09:00")
+            .unwrap()
+            .timestamp();
+
+        // each thread keeps its own cursor: the old owner comment does not move main's
+        let main_when = next_when(now, oldest_comment(std::slice::from_ref(&main)), 2);
+        assert_eq!(main_when, Some(main_oldest));
+        let owner_when = next_when(now, oldest_comment(std::slice::from_ref(&owner)), 1);
+        assert!(owner_when.unwrap() < main_oldest);
+
+        // nothing new on the page: the thread is exhausted
+        assert_eq!(next_when(now, Some(main_oldest), 0), None);
+        assert_eq!(next_when(now, None, 0), None);
+        // a page of comments all posted in the same second still moves back
+        assert_eq!(
+            next_when(main_oldest, Some(main_oldest), 3),
+            Some(main_oldest - 1)
+        );
+    }
+
+    #[test]
+    fn test_channel_videos() {
+        use crate::nicovideo::channel_videos;
+
+        let html = r#"
+            <div class="side"><a href="https://www.nicovideo.jp/watch/sm1">ranking</a></div>
+            <ul class="items">
+              <li class="item">
+                <a class="thumb_anchor" href="https://www.nicovideo.jp/watch/so2"><img></a>
+                <h6 class="title"><a href="https://www.nicovideo.jp/watch/so2?ref=ch" title="Episode 1">Episode 1</a></h6>
+              </li>
+              <li class="item">
+                <h6 class="title"><a href="https://www.nicovideo.jp/watch/so3">Episode 2</a></h6>
+              </li>
+            </ul>
+            <ul class="recommend"><li class="item"><a href="/watch/sm4">other</a></li></ul>
+        "#;
+        let videos = channel_videos(html);
+        let ids: Vec<&str> = videos.iter().map(|x| x.id.as_str()).collect();
+        assert_eq!(ids, ["so2", "so3"]);
+        assert_eq!(videos[0].title, "Episode 1");
+        assert_eq!(videos[1].title, "Episode 2");
+    }
+
+    #[test]
+    fn test_mylist_ids() {
+        use crate::nicovideo::mylist_ids;
+        use serde_json::json;
+
+        let json = json!({"data": {"mylists": [{"id": 12}, {"id": "34"}]}});
+        assert_eq!(
+            mylist_ids(&json),
+            Some(vec!["12".to_string(), "34".to_string()])
+        );
+        assert_eq!(
+            mylist_ids(&json!({"data": {"mylists": [{"id": null}]}})),
+            None
+        );
+        assert_eq!(mylist_ids(&json!({"data": {}})), None);
+    }
+
     #[test]
     fn test_totp() {
         use crate::nicovideo::compute_totp;
@@ -0,0 +1,94 @@
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+/// What to do when an output file already exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverwritePolicy {
+    Skip,
+    Overwrite,
+    Rename,
+    Ask,
+}
+
+impl OverwritePolicy {
+    /// Asks only when somebody can answer, otherwise keeps existing files.
+    pub fn detect() -> Self {
+        if io::stdin().is_terminal() {
+            OverwritePolicy::Ask
+        } else {
+            OverwritePolicy::Skip
+        }
+    }
+
+    /// Returns the path to write to, or `None` when the existing file should be kept.
+    pub fn resolve(&self, path: &Path) -> Result<Option<PathBuf>, io::Error> {
+        if !path.exists() {
+            return Ok(Some(path.to_path_buf()));
+        }
+        match self {
+            OverwritePolicy::Skip => {
+                println!("[-] '{}' is existed. skipping.", path.to_str().unwrap());
+                Ok(None)
+            }
+            OverwritePolicy::Overwrite => Ok(Some(path.to_path_buf())),
+            OverwritePolicy::Rename => Ok(Some(renamed(path))),
+            OverwritePolicy::Ask => {
+                print!(
+                    "[?] '{}' is existed. overwrite? [y/N]",
+                    path.to_str().unwrap()
+                );
+                io::stdout().flush()?;
+                let mut line = String::new();
+                io::stdin().read_line(&mut line)?;
+                if line.trim() == "y" {
+                    Ok(Some(path.to_path_buf()))
+                } else {
+                    Ok(None)
+                }
+            }
+        }
+    }
+}
+
+/// `foo.mp4` => `foo (1).mp4`, `foo (2).mp4`, ... whichever does not exist yet.
+fn renamed(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = path
+        .extension()
+        .map(|x| format!(".{}", x.to_string_lossy()))
+        .unwrap_or_default();
+    (1..)
+        .map(|i| path.with_file_name(format!("{} ({}){}", stem, i, ext)))
+        .find(|x| !x.exists())
+        .unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn test_rename() {
+        use crate::overwrite::OverwritePolicy;
+        use std::fs;
+
+        let dir = std::env::temp_dir().join(format!("nv_overwrite_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("sm9_title.mp4");
+        fs::write(&path, b"").unwrap();
+        fs::write(dir.join("sm9_title (1).mp4"), b"").unwrap();
+
+        let policy = OverwritePolicy::Rename;
+        assert_eq!(
+            policy.resolve(&path).unwrap(),
+            Some(dir.join("sm9_title (2).mp4"))
+        );
+        assert_eq!(OverwritePolicy::Skip.resolve(&path).unwrap(), None);
+        assert_eq!(
+            OverwritePolicy::Overwrite.resolve(&path).unwrap(),
+            Some(path.clone())
+        );
+        let fresh = dir.join("sm10.mp4");
+        assert_eq!(OverwritePolicy::Skip.resolve(&fresh).unwrap(), Some(fresh));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,209 @@
+use crate::api_data::{Domand, DomandAudio, DomandVideo};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VideoQuality {
+    Best,
+    Worst,
+    /// A domand label such as `720p`
+    Label(String),
+}
+
+impl FromStr for VideoQuality {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "best" => Ok(VideoQuality::Best),
+            "worst" => Ok(VideoQuality::Worst),
+            "" => Err("quality must not be empty".to_string()),
+            x => Ok(VideoQuality::Label(x.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioQuality {
+    Best,
+    Worst,
+    /// The best audio not exceeding `recommendedHighestAudioQualityLevel` of the chosen video
+    Recommended,
+}
+
+impl FromStr for AudioQuality {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "best" => Ok(AudioQuality::Best),
+            "worst" => Ok(AudioQuality::Worst),
+            "recommended" => Ok(AudioQuality::Recommended),
+            _ => Err(format!(
+                "unknown audio quality '{}' (best, worst or recommended)",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct QualitySelector {
+    pub video: VideoQuality,
+    pub max_height: Option<i32>,
+    /// in bps
+    pub max_bitrate: Option<i32>,
+    pub audio: AudioQuality,
+}
+
+impl QualitySelector {
+    /// Picks the video and audio to request from the domand server.
+    pub fn select<'a>(&self, domand: &'a Domand) -> Option<(&'a DomandVideo, &'a DomandAudio)> {
+        let video = self.select_video(&domand.videos)?;
+        let audio = self.select_audio(&domand.audios, video)?;
+        Some((video, audio))
+    }
+
+    pub fn select_video<'a>(&self, videos: &'a [DomandVideo]) -> Option<&'a DomandVideo> {
+        let mut candidates: Vec<&DomandVideo> = videos
+            .iter()
+            .filter(|x| x.isAvailable)
+            .filter(|x| self.max_height.is_none_or(|h| x.height <= h))
+            .filter(|x| self.max_bitrate.is_none_or(|b| x.bitRate <= b))
+            .collect();
+        candidates.sort_by_key(|x| x.qualityLevel);
+        match &self.video {
+            VideoQuality::Best => candidates.last().copied(),
+            VideoQuality::Worst => candidates.first().copied(),
+            VideoQuality::Label(label) => {
+                if let Some(x) = candidates
+                    .iter()
+                    .find(|x| x.label.eq_ignore_ascii_case(label))
+                {
+                    return Some(x);
+                }
+                // `720p` also means "the best one up to 720p" when there is no such label
+                let height: i32 = label.strip_suffix('p')?.parse().ok()?;
+                candidates.into_iter().rfind(|x| x.height <= height)
+            }
+        }
+    }
+
+    pub fn select_audio<'a>(
+        &self,
+        audios: &'a [DomandAudio],
+        video: &DomandVideo,
+    ) -> Option<&'a DomandAudio> {
+        let mut candidates: Vec<&DomandAudio> = audios.iter().filter(|x| x.isAvailable).collect();
+        candidates.sort_by_key(|x| x.qualityLevel);
+        match self.audio {
+            AudioQuality::Best => candidates.last().copied(),
+            AudioQuality::Worst => candidates.first().copied(),
+            AudioQuality::Recommended => candidates
+                .iter()
+                .rfind(|x| x.qualityLevel <= video.recommendedHighestAudioQualityLevel)
+                .or(candidates.first())
+                .copied(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::api_data::{DomandAudio, DomandVideo};
+    use crate::quality::{AudioQuality, QualitySelector, VideoQuality};
+
+    fn video(label: &str, height: i32, bit_rate: i32, level: i32, audio: i32) -> DomandVideo {
+        DomandVideo {
+            id: format!("video-h264-{}", label),
+            isAvailable: true,
+            label: label.to_string(),
+            bitRate: bit_rate,
+            width: height * 16 / 9,
+            height,
+            qualityLevel: level,
+            recommendedHighestAudioQualityLevel: audio,
+        }
+    }
+
+    fn audio(id: &str, bit_rate: i32, level: i32) -> DomandAudio {
+        DomandAudio {
+            id: id.to_string(),
+            isAvailable: true,
+            bitRate: bit_rate,
+            samplingRate: 48000,
+            integratedLoudness: -16.0,
+            truePeak: -1.0,
+            qualityLevel: level,
+            loudnessCollection: vec![],
+        }
+    }
+
+    fn selector(video: &str) -> QualitySelector {
+        QualitySelector {
+            video: video.parse().unwrap(),
+            max_height: None,
+            max_bitrate: None,
+            audio: AudioQuality::Best,
+        }
+    }
+
+    fn videos() -> Vec<DomandVideo> {
+        let mut videos = vec![
+            video("1080p", 1080, 6000000, 4, 1),
+            video("720p", 720, 3000000, 3, 1),
+            video("480p", 480, 1500000, 2, 0),
+            video("360p", 360, 800000, 1, 0),
+            video("144p", 144, 200000, 0, 0),
+        ];
+        videos[0].isAvailable = false; // premium only
+        videos
+    }
+
+    #[test]
+    fn test_select_video() {
+        let videos = videos();
+        let label = |s: &QualitySelector| s.select_video(&videos).map(|x| x.label.clone());
+
+        assert_eq!(label(&selector("best")), Some("720p".to_string()));
+        assert_eq!(label(&selector("worst")), Some("144p".to_string()));
+        assert_eq!(label(&selector("480P")), Some("480p".to_string()));
+        assert_eq!(label(&selector("1080p")), Some("720p".to_string()));
+        assert_eq!(label(&selector("540p")), Some("480p".to_string()));
+        assert_eq!(label(&selector("100p")), None);
+
+        let mut s = selector("best");
+        s.max_height = Some(500);
+        assert_eq!(label(&s), Some("480p".to_string()));
+        s.max_height = None;
+        s.max_bitrate = Some(1000000);
+        assert_eq!(label(&s), Some("360p".to_string()));
+    }
+
+    #[test]
+    fn test_select_audio() {
+        let videos = videos();
+        let audios = vec![
+            audio("audio-aac-64kbps", 64000, 0),
+            audio("audio-aac-192kbps", 192000, 1),
+        ];
+        let id =
+            |s: &QualitySelector, v: &DomandVideo| s.select_audio(&audios, v).map(|x| x.id.clone());
+
+        let mut s = selector("best");
+        assert_eq!(id(&s, &videos[1]), Some("audio-aac-192kbps".to_string()));
+        s.audio = AudioQuality::Worst;
+        assert_eq!(id(&s, &videos[1]), Some("audio-aac-64kbps".to_string()));
+        s.audio = AudioQuality::Recommended;
+        assert_eq!(id(&s, &videos[1]), Some("audio-aac-192kbps".to_string()));
+        assert_eq!(id(&s, &videos[3]), Some("audio-aac-64kbps".to_string()));
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("Best".parse(), Ok(VideoQuality::Best));
+        assert_eq!("720p".parse(), Ok(VideoQuality::Label("720p".to_string())));
+        assert!("".parse::<VideoQuality>().is_err());
+        assert_eq!("recommended".parse(), Ok(AudioQuality::Recommended));
+        assert!("loud".parse::<AudioQuality>().is_err());
+    }
+}
@@ -1,7 +1,7 @@
+use crate::http::HttpClient;
 use crate::{Error, UA_STRING};
-use futures_util::StreamExt;
+use reqwest::RequestBuilder;
 use reqwest::header::{ORIGIN, REFERER, USER_AGENT};
-use reqwest::{Client, Response};
 use reqwest_cookie_store::CookieStoreMutex;
 use serde::Serialize;
 use std::sync::Arc;
@@ -20,13 +20,25 @@ pub struct SeigaMetadata {
     pub comments: serde_json::Value,
 }
 
+impl SeigaMetadata {
+    /// `created_at` is shown in JST like `2014-05-04 21:10`.
+    pub fn created_at(&self) -> Option<chrono::DateTime<chrono::FixedOffset>> {
+        let jst = chrono::FixedOffset::east_opt(9 * 3600)?;
+        let date = self.created_at.trim();
+        let date = chrono::NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M")
+            .or_else(|_| chrono::NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S"))
+            .ok()?;
+        date.and_local_timezone(jst).single()
+    }
+}
+
 pub struct SeigaDownloader {
-    client: Arc<Client>,
+    client: Arc<HttpClient>,
     cookies: Arc<CookieStoreMutex>,
 }
 
 impl SeigaDownloader {
-    pub fn new(client: Arc<Client>, cookies: Arc<CookieStoreMutex>) -> Self {
+    pub fn new(client: Arc<HttpClient>, cookies: Arc<CookieStoreMutex>) -> Self {
         Self { client, cookies }
     }
 
@@ -54,28 +66,6 @@ impl SeigaDownloader {
                 .unwrap();
         }
 
-        let get_wrapper = |url: String, label| async move {
-            Ok(loop {
-                break match self.get_raw_html(&url).await {
-                    Ok(x) => x,
-                    Err(Error::ReqwestError(e)) => {
-                        if e.is_connect() {
-                            if crate::is_debug() {
-                                println!(
-                                    "[{label}] Server has been reset a connection, wait and retry..."
-                                );
-                                dbg!(&url);
-                            }
-                            Self::sleep_sec(10).await;
-                            continue;
-                        }
-                        return Err(Error::ReqwestError(e));
-                    }
-                    Err(e) => return Err(e),
-                };
-            })
-        };
-
         let image_id = if image_id.starts_with("im") {
             image_id.strip_prefix("im").unwrap()
         } else {
@@ -83,7 +73,7 @@ impl SeigaDownloader {
         };
 
         let url = format!("https://seiga.nicovideo.jp/seiga/im{image_id}");
-        let html = get_wrapper(url, "seiga_page").await?;
+        let html = self.get_raw_html(&url).await?;
         if html.contains("ページが見つかりません") {
             // deleted image
             return Ok(None);
@@ -197,117 +187,50 @@ impl SeigaDownloader {
 
         // Get Tag list
         let url = format!("https://seiga.nicovideo.jp/ajax/illust/tag/list?id={image_id}");
-        let json = get_wrapper(url, "tag").await?;
-        let tags: serde_json::Value = loop {
-            break match serde_json::from_str(&json) {
-                Ok(x) => x,
-                Err(e) => {
-                    println!("[Tag list] Server returned an invalid json, wait and retry...");
-                    if crate::is_debug() {
-                        dbg!(e);
-                    }
-                    Self::sleep_sec(10).await;
-                    continue;
-                }
-            };
-        };
+        let tags: serde_json::Value = self
+            .get_checked(&url, "Tag list", |x| serde_json::from_slice(x).ok())
+            .await?;
+        let is_oekakiko = tags["tag_list"]
+            .as_array()
+            .is_some_and(|x| x.iter().any(|x| x["name"] == "お絵カキコ"));
 
         // Get image blob
         // Decide a url that points image blob
         let url = format!("https://seiga.nicovideo.jp/image/source/{image_id}");
-        let image_url = loop {
-            let html = get_wrapper(url.clone(), "seiga_source_page").await?;
-            break if html.as_bytes()[..6] == [0xef, 0xbf, 0xbd, 0x50, 0x4e, 0x47] {
-                // oekakiko (new)
-                // \x89 => \xef\xbf\xbd (U+FFFD REPLACEMENT CHARACTER)
-                url
-            } else {
-                let lines: Vec<&str> = html.split("\n").collect();
-                if tags["tag_list"]
-                    .as_array()
-                    .unwrap()
-                    .iter()
-                    .any(|x| x["name"].as_str().unwrap() == "お絵カキコ")
-                {
+        let image_url = self
+            .get_checked(&url, "Image source", |x| {
+                if x.starts_with(b"\x89PNG") {
+                    // oekakiko (new)
+                    Some(url.clone())
+                } else if is_oekakiko {
                     // oekakiko
-                    thumbnail_url.to_string()
+                    Some(thumbnail_url.to_string())
                 } else {
-                    let Some(x) = lines
-                        .iter()
-                        .filter(|x| x.contains("data-src"))
-                        .map(|x| x.split("\"").skip(1).take(1).last().unwrap())
-                        .take(1)
-                        .last()
-                    else {
-                        println!("Server returned an invalid html, wait and retry...");
-                        if crate::is_debug() {
-                            dbg!(image_id);
-                            dbg!(&html.as_bytes()[..6]);
-                            // println!("html = {}", html);
-                        }
-                        Self::sleep_sec(10).await;
-                        continue;
-                    };
-
-                    x.to_string()
+                    String::from_utf8_lossy(x)
+                        .lines()
+                        .find(|x| x.contains("data-src"))
+                        .and_then(|x| x.split('"').nth(1))
+                        .map(|x| x.to_string())
                 }
-            };
-        };
+            })
+            .await?;
 
         // Download the blob
-        let mut v = vec![];
-        loop {
-            let mut stream = match self.get(&image_url).await {
-                Ok(x) => x,
-                Err(Error::ReqwestError(e)) => {
-                    if e.is_connect() {
-                        if crate::is_debug() {
-                            println!(
-                                "[blob] Server has been reset a connection, wait and retry..."
-                            );
-                        }
-                        Self::sleep_sec(10).await;
-                        continue;
-                    }
-                    return Err(Error::ReqwestError(e));
-                }
-                Err(e) => return Err(e),
-            }
-            .bytes_stream();
-            while let Some(item) = stream.next().await {
-                v.extend_from_slice(&item.unwrap());
-            }
-            if &v[..6] == "<html>".as_bytes() {
-                // 503
-                if crate::is_debug() {
-                    println!("Server returns 503, wait and retry...");
-                }
-                Self::sleep_sec(8).await;
-                v = vec![];
-                continue;
-            }
-            break;
-        }
+        // the server answers with a 503 page and status 200 when it is busy
+        let v = self
+            .get_checked(&image_url, "Image", |x| {
+                (!x.starts_with(b"<html>")).then(|| x.to_vec())
+            })
+            .await?;
 
         Self::sleep_sec(1).await;
 
         // Get Comment list
         let url =
             format!("https://seiga.nicovideo.jp/ajax/illust/comment/list?id={image_id}&mode=all");
-        let json = get_wrapper(url, "comment").await?;
-        let comments: serde_json::Value = loop {
-            break match serde_json::from_str(&json) {
-                Ok(x) => x,
-                Err(e) => {
-                    println!("[comment] Server returned an invalid json, wait and retry...");
-                    if crate::is_debug() {
-                        dbg!(e);
-                    }
-                    Self::sleep_sec(10).await;
-                    continue;
-                }
-            };
-        };
+        let comments: serde_json::Value = self
+            .get_checked(&url, "Comment list", |x| serde_json::from_slice(x).ok())
+            .await?;
         Self::sleep_sec(5).await;
 
         // Combine and return
@@ -433,21 +356,57 @@ impl SeigaDownloader {
         Ok((images, next_page))
     }
 
-    async fn get(&self, url: &str) -> Result<Response, Error> {
-        let res = self
-            .client
+    fn get(&self, url: &str) -> RequestBuilder {
+        self.client
             .get(url)
             .header(REFERER, "https://www.nicovideo.jp")
             .header(ORIGIN, "https://www.nicovideo.jp")
             .header(USER_AGENT, UA_STRING)
-            .send()
-            .await?;
-        Ok(res)
+    }
+
+    /// Fetches `url` until `parse` accepts the body. Seiga answers some failures with an
+    /// error page and a 200 status, which `HttpClient` can not tell from content.
+    async fn get_checked<T>(
+        &self,
+        url: &str,
+        name: &str,
+        parse: impl Fn(&[u8]) -> Option<T>,
+    ) -> Result<T, Error> {
+        let retry = self.client.retry_policy();
+        let mut attempt = 0;
+        loop {
+            let body = self
+                .client
+                .fetch(self.get(url), |res| async move { Ok(res.bytes().await?) })
+                .await?;
+            if let Some(x) = parse(&body) {
+                return Ok(x);
+            }
+            if crate::is_debug() {
+                dbg!(String::from_utf8_lossy(&body[..body.len().min(64)]));
+            }
+            if attempt >= retry.retries {
+                println!(
+                    "[-] [{}] Server returned an invalid response, giving up",
+                    name
+                );
+                return Err(Error::DownloadError);
+            }
+            let wait = retry.backoff(attempt);
+            attempt += 1;
+            println!(
+                "[-] [{}] Server returned an invalid response, retry {}/{} in {:.1}s",
+                name,
+                attempt,
+                retry.retries,
+                wait.as_secs_f64()
+            );
+            tokio::time::sleep(wait).await;
+        }
     }
 
     async fn get_raw_html(&self, url: &str) -> Result<String, Error> {
-        let raw_html = self.get(url).await?.text().await?;
-        Ok(raw_html)
+        self.client.send_text(self.get(url)).await
     }
 
     async fn sleep_sec(sec: u64) {
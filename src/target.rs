@@ -0,0 +1,341 @@
+use std::fmt;
+use std::str::FromStr;
+use url::Url;
+
+/// A download target resolved from an id, a legacy prefix form or a URL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    Video(String),
+    Series(String),
+    Seiga(String),
+    Clip(String),
+    SeigaTag(String),
+    User(String),
+    Mylist(String),
+    /// A channel by its `ch` id or slug
+    Channel(String),
+    /// The logged-in user's watch-later list
+    WatchLater,
+    /// All mylists of the logged-in user
+    MyMylists,
+    /// The logged-in user's like history
+    Likes,
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Video(id) => write!(f, "{}", id),
+            Target::Series(id) => write!(f, "series/{}", id),
+            Target::Seiga(id) => write!(f, "{}", id),
+            Target::Clip(id) => write!(f, "clip/{}", id),
+            Target::SeigaTag(tag) => write!(f, "seiga-tag#{}", tag),
+            Target::User(id) => write!(f, "user/{}", id),
+            Target::Mylist(id) => write!(f, "mylist/{}", id),
+            Target::Channel(id) => write!(f, "channel/{}", id),
+            Target::WatchLater => write!(f, "my/watch-later"),
+            Target::MyMylists => write!(f, "my/mylists"),
+            Target::Likes => write!(f, "my/likes"),
+        }
+    }
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.starts_with("http://") || s.starts_with("https://") {
+            let url = Url::parse(s).map_err(|e| format!("invalid URL '{}': {}", s, e))?;
+            return from_url(&url).ok_or_else(|| format!("unsupported URL: {}", s));
+        }
+        if let Some(id) = s.strip_prefix("series/") {
+            return Ok(Target::Series(id.to_string()));
+        }
+        if let Some(id) = s.strip_prefix("user/") {
+            return Ok(Target::User(id.to_string()));
+        }
+        match s {
+            "my/watch-later" => return Ok(Target::WatchLater),
+            "my/mylists" => return Ok(Target::MyMylists),
+            "my/likes" => return Ok(Target::Likes),
+            _ => {}
+        }
+        if let Some(id) = s.strip_prefix("channel/") {
+            return Ok(Target::Channel(id.to_string()));
+        }
+        if let Some(id) = s.strip_prefix("mylist/") {
+            return mylist(id).ok_or_else(|| format!("invalid mylist id: {}", id));
+        }
+        if let Some(id) = s.strip_prefix("clip/") {
+            return Ok(Target::Clip(id.to_string()));
+        }
+        if let Some(tag) = s.strip_prefix("seiga-tag#") {
+            return Ok(Target::SeigaTag(tag.to_string()));
+        }
+        from_id(s).ok_or_else(|| format!("unknown target: {}", s))
+    }
+}
+
+fn from_id(id: &str) -> Option<Target> {
+    if id.starts_with("sm") || id.starts_with("nm") || id.starts_with("so") {
+        return Some(Target::Video(id.to_string()));
+    }
+    if id.starts_with("im") {
+        return Some(Target::Seiga(id.to_string()));
+    }
+    if is_channel_id(id) {
+        return Some(Target::Channel(id.to_string()));
+    }
+    None
+}
+
+/// `ch` followed by digits, as opposed to a channel slug or a page of ch.nicovideo.jp.
+fn is_channel_id(id: &str) -> bool {
+    id.len() > 2 && id.starts_with("ch") && id[2..].chars().all(|x| x.is_ascii_digit())
+}
+
+/// Mylists are only addressed by their numeric id.
+fn mylist(id: &str) -> Option<Target> {
+    if !id.is_empty() && id.chars().all(|x| x.is_ascii_digit()) {
+        return Some(Target::Mylist(id.to_string()));
+    }
+    None
+}
+
+fn from_url(url: &Url) -> Option<Target> {
+    let segments: Vec<String> = url
+        .path_segments()?
+        .filter(|x| !x.is_empty())
+        .map(|x| {
+            percent_encoding::percent_decode_str(x)
+                .decode_utf8_lossy()
+                .to_string()
+        })
+        .collect();
+    let segments: Vec<&str> = segments.iter().map(|x| x.as_str()).collect();
+
+    match url.host_str()? {
+        "nico.ms" => match segments[..] {
+            [id] => from_id(id),
+            _ => None,
+        },
+        "www.nicovideo.jp" | "nicovideo.jp" | "sp.nicovideo.jp" => match segments[..] {
+            ["watch", id, ..] => Some(Target::Video(id.to_string())),
+            ["series", id, ..] | ["user", _, "series", id, ..] => {
+                Some(Target::Series(id.to_string()))
+            }
+            ["my", "watchlater", ..] => Some(Target::WatchLater),
+            ["my", "mylist"] => Some(Target::MyMylists),
+            ["my", "history", "like", ..] => Some(Target::Likes),
+            ["mylist", id, ..] | ["my", "mylist", id, ..] | ["user", _, "mylist", id, ..] => {
+                mylist(id)
+            }
+            ["user", id] | ["user", id, "video", ..] => Some(Target::User(id.to_string())),
+            _ => None,
+        },
+        "ch.nicovideo.jp" | "sp.ch.nicovideo.jp" => match segments[..] {
+            // other single-segment paths are pages of the site, e.g. `/search`
+            [id] if is_channel_id(id) => Some(Target::Channel(id.to_string())),
+            ["channel", id, ..] | [id, "video", ..] => Some(Target::Channel(id.to_string())),
+            _ => None,
+        },
+        "seiga.nicovideo.jp" | "sp.seiga.nicovideo.jp" => match segments[..] {
+            ["seiga", id, ..] => Some(Target::Seiga(id.to_string())),
+            ["tag", tag, ..] => Some(Target::SeigaTag(tag.to_string())),
+            ["clip", id, ..] => Some(Target::Clip(id.to_string())),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::target::Target;
+
+    fn resolve(s: &str) -> Target {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_watch_url() {
+        let expected = Target::Video("sm123".to_string());
+        assert_eq!(resolve("https://www.nicovideo.jp/watch/sm123"), expected);
+        assert_eq!(
+            resolve("https://www.nicovideo.jp/watch/sm123?ref=nicotop_series"),
+            expected
+        );
+        assert_eq!(
+            resolve("https://sp.nicovideo.jp/watch/sm123#t=10"),
+            expected
+        );
+        assert_eq!(resolve("http://nicovideo.jp/watch/sm123/"), expected);
+    }
+
+    #[test]
+    fn test_short_url() {
+        assert_eq!(
+            resolve("https://nico.ms/sm123"),
+            Target::Video("sm123".to_string())
+        );
+        assert_eq!(
+            resolve("https://nico.ms/nm45?cp_in=wt_tw"),
+            Target::Video("nm45".to_string())
+        );
+        assert_eq!(
+            resolve("https://nico.ms/im789"),
+            Target::Seiga("im789".to_string())
+        );
+    }
+
+    #[test]
+    fn test_series_url() {
+        let expected = Target::Series("456".to_string());
+        assert_eq!(resolve("https://www.nicovideo.jp/series/456"), expected);
+        assert_eq!(
+            resolve("https://www.nicovideo.jp/user/1/series/456?ref=pc_watch_description"),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_user_url() {
+        let expected = Target::User("1".to_string());
+        assert_eq!(resolve("https://www.nicovideo.jp/user/1"), expected);
+        assert_eq!(
+            resolve("https://www.nicovideo.jp/user/1/video?ref=pc_userpage_menu"),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_mylist_url() {
+        let expected = Target::Mylist("789".to_string());
+        assert_eq!(resolve("https://www.nicovideo.jp/mylist/789"), expected);
+        assert_eq!(
+            resolve("https://www.nicovideo.jp/user/1/mylist/789?ref=pc_mypage_menu"),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_my_url() {
+        assert_eq!(
+            resolve("https://www.nicovideo.jp/my/watchlater?ref=pc_mypage_menu"),
+            Target::WatchLater
+        );
+        assert_eq!(
+            resolve("https://www.nicovideo.jp/my/mylist"),
+            Target::MyMylists
+        );
+        assert_eq!(
+            resolve("https://www.nicovideo.jp/my/mylist/789"),
+            Target::Mylist("789".to_string())
+        );
+        assert_eq!(
+            resolve("https://www.nicovideo.jp/my/history/like"),
+            Target::Likes
+        );
+    }
+
+    #[test]
+    fn test_channel_url() {
+        assert_eq!(
+            resolve("https://ch.nicovideo.jp/ch2525"),
+            Target::Channel("ch2525".to_string())
+        );
+        assert_eq!(
+            resolve("https://ch.nicovideo.jp/some-channel/video?page=2"),
+            Target::Channel("some-channel".to_string())
+        );
+        assert_eq!(
+            resolve("https://nico.ms/ch2525"),
+            Target::Channel("ch2525".to_string())
+        );
+        assert_eq!(
+            resolve("https://www.nicovideo.jp/watch/so123"),
+            Target::Video("so123".to_string())
+        );
+    }
+
+    #[test]
+    fn test_seiga_url() {
+        assert_eq!(
+            resolve("https://seiga.nicovideo.jp/seiga/im789?track=seiga_illust_keyword"),
+            Target::Seiga("im789".to_string())
+        );
+        assert_eq!(
+            resolve("https://seiga.nicovideo.jp/clip/1234"),
+            Target::Clip("1234".to_string())
+        );
+    }
+
+    #[test]
+    fn test_seiga_tag_url() {
+        assert_eq!(
+            resolve(
+                "https://seiga.nicovideo.jp/tag/%E3%81%8A%E7%B5%B5%E3%82%AB%E3%82%AD%E3%82%B3?target=illust_all"
+            ),
+            Target::SeigaTag("お絵カキコ".to_string())
+        );
+    }
+
+    #[test]
+    fn test_plain_target() {
+        assert_eq!(resolve("sm9"), Target::Video("sm9".to_string()));
+        assert_eq!(resolve("im10"), Target::Seiga("im10".to_string()));
+        assert_eq!(resolve("series/11"), Target::Series("11".to_string()));
+        assert_eq!(resolve("clip/12"), Target::Clip("12".to_string()));
+        assert_eq!(resolve("user/13"), Target::User("13".to_string()));
+        assert_eq!(resolve("mylist/14"), Target::Mylist("14".to_string()));
+        assert_eq!(resolve("so15"), Target::Video("so15".to_string()));
+        assert_eq!(resolve("ch16"), Target::Channel("ch16".to_string()));
+        assert_eq!(
+            resolve("channel/some-channel"),
+            Target::Channel("some-channel".to_string())
+        );
+        assert_eq!(
+            resolve("seiga-tag#VOCALOID"),
+            Target::SeigaTag("VOCALOID".to_string())
+        );
+    }
+
+    #[test]
+    fn test_display_roundtrip() {
+        for s in [
+            "sm9",
+            "im10",
+            "series/11",
+            "clip/12",
+            "user/13",
+            "mylist/14",
+            "channel/some-channel",
+            "my/watch-later",
+            "my/mylists",
+            "my/likes",
+            "seiga-tag#VOCALOID",
+        ] {
+            assert_eq!(resolve(s).to_string(), s);
+        }
+    }
+
+    #[test]
+    fn test_unsupported() {
+        assert!("https://example.com/watch/sm9".parse::<Target>().is_err());
+        assert!(
+            "https://www.nicovideo.jp/ranking"
+                .parse::<Target>()
+                .is_err()
+        );
+        assert!("lv123".parse::<Target>().is_err());
+        assert!("https://ch.nicovideo.jp/search".parse::<Target>().is_err());
+        assert!("mylist/".parse::<Target>().is_err());
+        assert!("mylist/abc".parse::<Target>().is_err());
+        assert!(
+            "https://www.nicovideo.jp/mylist/12a"
+                .parse::<Target>()
+                .is_err()
+        );
+    }
+}
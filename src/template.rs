@@ -0,0 +1,479 @@
+use crate::api_data::ApiData;
+use crate::mylist::Mylist;
+use crate::series::Series;
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, FixedOffset};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Longest file name in bytes. Most filesystems allow 255, the rest is left for
+/// the ` (N)` suffix of `--rename` and the `.part`-like suffixes of other tools.
+const MAX_NAME_LEN: usize = 240;
+
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Id,
+    Title,
+    Owner,
+    OwnerId,
+    Date,
+    Series,
+    SeriesIndex,
+    Quality,
+    Group,
+    Ext,
+}
+
+impl Field {
+    /// Fields shortened to fit the name length limit, in this order.
+    const TRUNCATABLE: [Field; 4] = [Field::Title, Field::Series, Field::Owner, Field::Group];
+
+    fn parse(name: &str) -> Option<Field> {
+        Some(match name {
+            "id" => Field::Id,
+            "title" => Field::Title,
+            "owner" => Field::Owner,
+            "owner_id" => Field::OwnerId,
+            "date" => Field::Date,
+            "series" => Field::Series,
+            "series_index" => Field::SeriesIndex,
+            "quality" => Field::Quality,
+            "group" => Field::Group,
+            "ext" => Field::Ext,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Literal(String),
+    /// A field with its strftime format for `{date:...}`
+    Field(Field, Option<String>),
+}
+
+/// Output path template such as `{owner}/{date:%Y%m%d}_{title}.{ext}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    absolute: bool,
+    /// `/`-separated path components
+    components: Vec<Vec<Token>>,
+}
+
+impl FromStr for Template {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut components = vec![];
+        let mut tokens = vec![];
+        let mut literal = String::new();
+        let mut chars = s.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut spec = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => spec.push(c),
+                            None => return Err(format!("unclosed '{{' in '{}'", s)),
+                        }
+                    }
+                    let (name, format) = match spec.split_once(':') {
+                        Some((name, format)) => (name, Some(format.to_string())),
+                        None => (spec.as_str(), None),
+                    };
+                    let field = Field::parse(name)
+                        .ok_or_else(|| format!("unknown field '{{{}}}' in '{}'", name, s))?;
+                    if let Some(format) = &format {
+                        if field != Field::Date {
+                            return Err(format!("only {{date}} takes a format in '{}'", s));
+                        }
+                        if StrftimeItems::new(format).any(|x| matches!(x, Item::Error)) {
+                            return Err(format!("invalid date format '{}'", format));
+                        }
+                    }
+                    if !literal.is_empty() {
+                        tokens.push(Token::Literal(std::mem::take(&mut literal)));
+                    }
+                    tokens.push(Token::Field(field, format));
+                }
+                '}' => return Err(format!("unmatched '}}' in '{}'", s)),
+                '/' => {
+                    if !literal.is_empty() {
+                        tokens.push(Token::Literal(std::mem::take(&mut literal)));
+                    }
+                    components.push(std::mem::take(&mut tokens));
+                }
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            tokens.push(Token::Literal(literal));
+        }
+        components.push(tokens);
+
+        let absolute = s.starts_with('/');
+        components.retain(|x| !x.is_empty());
+        if components.is_empty() {
+            return Err("output template must not be empty".to_string());
+        }
+        // one template renders several files told apart by the extension only, e.g. comments
+        let has_ext = components
+            .iter()
+            .flatten()
+            .any(|x| matches!(x, Token::Field(Field::Ext, _)));
+        if !has_ext {
+            let last = components.last_mut().unwrap();
+            last.push(Token::Literal(".".to_string()));
+            last.push(Token::Field(Field::Ext, None));
+        }
+        Ok(Template {
+            absolute,
+            components,
+        })
+    }
+}
+
+/// Values substituted into a `Template`.
+#[derive(Debug, Clone, Default)]
+pub struct Vars {
+    pub id: String,
+    pub title: String,
+    pub owner: String,
+    pub owner_id: String,
+    pub date: Option<DateTime<FixedOffset>>,
+    pub series: String,
+    pub series_index: Option<usize>,
+    pub quality: String,
+    /// Seiga tag or clip the image was found in, e.g. `tag_東方`
+    pub group: String,
+}
+
+impl Vars {
+    pub fn video(api_data: &ApiData, series_index: Option<usize>) -> Self {
+        Vars {
+            id: api_data.video.id.clone(),
+            title: api_data.video.title.clone(),
+            owner: api_data.owner_name().unwrap_or_default().to_string(),
+            owner_id: api_data.owner_id().unwrap_or_default(),
+            date: DateTime::parse_from_rfc3339(&api_data.video.registeredAt).ok(),
+            series: api_data
+                .series
+                .as_ref()
+                .map(|x| x.title.clone())
+                .unwrap_or_default(),
+            series_index,
+            ..Default::default()
+        }
+    }
+
+    pub fn series(series: &Series) -> Self {
+        Vars {
+            id: series.id.to_string(),
+            title: series.title.clone(),
+            owner: series.owner_name.clone(),
+            owner_id: series.owner.to_string(),
+            date: DateTime::parse_from_rfc3339(&series.created_at).ok(),
+            series: series.title.clone(),
+            ..Default::default()
+        }
+    }
+
+    pub fn mylist(mylist: &Mylist) -> Self {
+        Vars {
+            id: mylist.id.to_string(),
+            title: mylist.name.clone(),
+            owner: mylist.owner_name.clone(),
+            owner_id: mylist.owner.clone(),
+            ..Default::default()
+        }
+    }
+
+    fn value(&self, field: Field, format: Option<&str>, ext: &str) -> String {
+        let value = match field {
+            Field::Id => self.id.clone(),
+            Field::Title => self.title.clone(),
+            Field::Owner => self.owner.clone(),
+            Field::OwnerId => self.owner_id.clone(),
+            Field::Date => self
+                .date
+                .map(|x| x.format(format.unwrap_or(DEFAULT_DATE_FORMAT)).to_string())
+                .unwrap_or_default(),
+            Field::Series => self.series.clone(),
+            Field::SeriesIndex => self.series_index.map(|x| x.to_string()).unwrap_or_default(),
+            Field::Quality => self.quality.clone(),
+            Field::Group => self.group.clone(),
+            Field::Ext => return ext.to_string(),
+        };
+        sanitize_filename::sanitize(value)
+    }
+}
+
+/// Cuts `s` to at most `len` bytes on a char boundary.
+fn truncate(s: &mut String, len: usize) {
+    let mut end = len.min(s.len());
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    s.truncate(end);
+    s.truncate(s.trim_end().len());
+}
+
+impl Template {
+    /// Whether the template only uses fields known before fetching anything but the id.
+    pub fn needs_only_id(&self) -> bool {
+        self.components.iter().flatten().all(|x| match x {
+            Token::Literal(_) => true,
+            Token::Field(field, _) => matches!(field, Field::Id | Field::Group | Field::Ext),
+        })
+    }
+
+    pub fn uses_series_index(&self) -> bool {
+        self.components
+            .iter()
+            .flatten()
+            .any(|x| matches!(x, Token::Field(Field::SeriesIndex, _)))
+    }
+
+    /// Renders the path, shortening long fields so that every file name fits the limit.
+    pub fn render(&self, vars: &Vars, ext: &str) -> PathBuf {
+        let mut path = PathBuf::new();
+        if self.absolute {
+            path.push("/");
+        }
+        for component in &self.components {
+            let mut values: Vec<String> = component
+                .iter()
+                .map(|x| match x {
+                    Token::Literal(x) => x.clone(),
+                    Token::Field(field, format) => vars.value(*field, format.as_deref(), ext),
+                })
+                .collect();
+            for field in Field::TRUNCATABLE {
+                let len: usize = values.iter().map(|x| x.len()).sum();
+                if len <= MAX_NAME_LEN {
+                    break;
+                }
+                let mut over = len - MAX_NAME_LEN;
+                for (token, value) in component.iter().zip(values.iter_mut()) {
+                    if matches!(token, Token::Field(x, _) if *x == field) && over > 0 {
+                        let before = value.len();
+                        truncate(value, before.saturating_sub(over));
+                        over = over.saturating_sub(before - value.len());
+                    }
+                }
+            }
+            let mut name = values.concat();
+            if name.len() > MAX_NAME_LEN {
+                // nothing left to shorten but the literals: keep the extension at least
+                let suffix = format!(".{}", ext);
+                if name.ends_with(&suffix) && suffix.len() < MAX_NAME_LEN {
+                    name.truncate(name.len() - suffix.len());
+                    truncate(&mut name, MAX_NAME_LEN - suffix.len());
+                    name.push_str(&suffix);
+                } else {
+                    truncate(&mut name, MAX_NAME_LEN);
+                }
+            }
+            if !name.is_empty() {
+                path.push(name);
+            }
+        }
+        path
+    }
+}
+
+/// Artifacts whose paths are given by `--output KIND:TEMPLATE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputKind {
+    Video,
+    Thumbnail,
+    Nfo,
+    Comments,
+    Metadata,
+    Series,
+    Tvshow,
+    Mylist,
+    Seiga,
+    SeigaMetadata,
+}
+
+impl OutputKind {
+    const ALL: [OutputKind; 10] = [
+        OutputKind::Video,
+        OutputKind::Thumbnail,
+        OutputKind::Nfo,
+        OutputKind::Comments,
+        OutputKind::Metadata,
+        OutputKind::Series,
+        OutputKind::Tvshow,
+        OutputKind::Mylist,
+        OutputKind::Seiga,
+        OutputKind::SeigaMetadata,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            OutputKind::Video => "video",
+            OutputKind::Thumbnail => "thumbnail",
+            OutputKind::Nfo => "nfo",
+            OutputKind::Comments => "comments",
+            OutputKind::Metadata => "metadata",
+            OutputKind::Series => "series",
+            OutputKind::Tvshow => "tvshow",
+            OutputKind::Mylist => "mylist",
+            OutputKind::Seiga => "seiga",
+            OutputKind::SeigaMetadata => "seiga-metadata",
+        }
+    }
+
+    fn default_template(&self) -> &'static str {
+        match self {
+            OutputKind::Video | OutputKind::Thumbnail | OutputKind::Nfo => "{id}_{title}.{ext}",
+            OutputKind::Comments => "comments/{id}.{ext}",
+            OutputKind::Metadata => "metadata/{id}.{ext}",
+            OutputKind::Series => "series/{id}.{ext}",
+            OutputKind::Tvshow => "series/{id}/tvshow.{ext}",
+            OutputKind::Mylist => "mylist/{id}.{ext}",
+            OutputKind::Seiga => "seiga/{group}/{id}.{ext}",
+            OutputKind::SeigaMetadata => "seiga/metadata/{id}.{ext}",
+        }
+    }
+}
+
+/// Parses `[KIND:]TEMPLATE` of `--output`, the kind defaulting to `video`. A prefix which
+/// is not a kind name is part of the path, e.g. the drive of `C:\dl\{id}.{ext}`.
+pub fn parse_output(s: &str) -> Result<(OutputKind, Template), String> {
+    let (kind, template) = s
+        .split_once(':')
+        .and_then(|(kind, template)| {
+            let kind = OutputKind::ALL.into_iter().find(|x| x.name() == kind)?;
+            Some((kind, template))
+        })
+        .unwrap_or((OutputKind::Video, s));
+    Ok((kind, template.parse()?))
+}
+
+#[derive(Debug, Clone)]
+pub struct OutputTemplates(Vec<(OutputKind, Template)>);
+
+impl OutputTemplates {
+    pub fn new(templates: Vec<(OutputKind, Template)>) -> Self {
+        Self(templates)
+    }
+
+    /// The last template given for `kind`. Thumbnails and NFOs follow the video by default.
+    pub fn get(&self, kind: OutputKind) -> Template {
+        let given = |kind| {
+            self.0
+                .iter()
+                .rev()
+                .find(|(x, _)| *x == kind)
+                .map(|(_, x)| x.clone())
+        };
+        given(kind)
+            .or_else(|| match kind {
+                OutputKind::Thumbnail | OutputKind::Nfo => given(OutputKind::Video),
+                _ => None,
+            })
+            .unwrap_or_else(|| kind.default_template().parse().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::template::{OutputKind, OutputTemplates, Template, Vars, parse_output};
+    use std::path::PathBuf;
+
+    fn vars() -> Vars {
+        Vars {
+            id: "sm9".to_string(),
+            title: "新・豪血寺一族 -煩悩解放 - レッツゴー！陰陽師".to_string(),
+            owner: "中の/人".to_string(),
+            owner_id: "4".to_string(),
+            date: chrono::DateTime::parse_from_rfc3339("2007-03-06T00:33:00+09:00").ok(),
+            quality: "720p".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let render = |s: &str| s.parse::<Template>().unwrap().render(&vars(), "mp4");
+
+        assert_eq!(
+            render("{id}_{title}.{ext}"),
+            PathBuf::from("sm9_新・豪血寺一族 -煩悩解放 - レッツゴー！陰陽師.mp4")
+        );
+        assert_eq!(
+            render("{owner}/{date:%Y%m%d}-{id} [{quality}]{{x}}.{ext}"),
+            PathBuf::from("中の人/20070306-sm9 [720p]{x}.mp4")
+        );
+        assert_eq!(
+            render("/tmp/{date}/{series}/{id}.{ext}"),
+            PathBuf::from("/tmp/2007-03-06/sm9.mp4")
+        );
+        assert_eq!(render("{id}"), PathBuf::from("sm9.mp4"));
+        assert_eq!(render("{id}.mkv"), PathBuf::from("sm9.mkv.mp4"));
+
+        assert!(
+            "{series}/{series_index}.{ext}"
+                .parse::<Template>()
+                .unwrap()
+                .uses_series_index()
+        );
+        assert!(!"{id}".parse::<Template>().unwrap().uses_series_index());
+
+        assert!("{nope}".parse::<Template>().is_err());
+        assert!("{id:%Y}".parse::<Template>().is_err());
+        assert!("{date:%Q}".parse::<Template>().is_err());
+        assert!("{id}}".parse::<Template>().is_err());
+        assert!("{title".parse::<Template>().is_err());
+        assert!("{id_{title}.{ext}".parse::<Template>().is_err());
+    }
+
+    #[test]
+    fn test_truncate() {
+        let template: Template = "{id}_{title} ({owner}).{ext}".parse().unwrap();
+        let mut vars = vars();
+        vars.title = "あ".repeat(200);
+        let path = template.render(&vars, "mp4");
+        let name = path.to_str().unwrap();
+        assert!(name.len() <= 240, "{}", name.len());
+        assert!(name.starts_with("sm9_あ"));
+        assert!(name.ends_with("あ (中の人).mp4"));
+    }
+
+    #[test]
+    fn test_output() {
+        let (kind, template) = parse_output("{date:%H:%M}.{ext}").unwrap();
+        assert_eq!(kind, OutputKind::Video);
+        assert_eq!(template, "{date:%H:%M}.{ext}".parse().unwrap());
+        assert_eq!(parse_output("nfo:a.{ext}").unwrap().0, OutputKind::Nfo);
+        let (kind, template) = parse_output("C:\\dl\\{id}.{ext}").unwrap();
+        assert_eq!(kind, OutputKind::Video);
+        assert_eq!(
+            template.render(&vars(), "mp4"),
+            PathBuf::from("C:\\dl\\sm9.mp4")
+        );
+        assert_eq!(parse_output("a:b/{id}").unwrap().0, OutputKind::Video);
+
+        let outputs = OutputTemplates::new(vec![parse_output("{id}.{ext}").unwrap()]);
+        let path = |kind| outputs.get(kind).render(&vars(), "x");
+        assert_eq!(path(OutputKind::Video), PathBuf::from("sm9.x"));
+        assert_eq!(path(OutputKind::Thumbnail), PathBuf::from("sm9.x"));
+        assert_eq!(path(OutputKind::Comments), PathBuf::from("comments/sm9.x"));
+        assert_eq!(path(OutputKind::Seiga), PathBuf::from("seiga/sm9.x"));
+    }
+}
@@ -0,0 +1,19 @@
+use serde::{Deserialize, Serialize};
+
+/// `essential` of an item of the nvapi user videos listing.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all(deserialize = "camelCase"))]
+pub struct UserVideo {
+    pub id: String,
+    pub title: String,
+    pub registered_at: String,
+    pub duration: i64,
+}
+
+/// Videos uploaded by a user, newest first.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct UserVideos {
+    pub id: String,
+    pub total_count: i64,
+    pub items: Vec<UserVideo>,
+}